@@ -0,0 +1,115 @@
+/// Everything the CPU reads and writes goes through a bus: instruction fetches, sprite
+/// data, Fx33, Fx55 and Fx65 and an in-memory stack. [`Ram`] is plain memory, other
+/// implementations can add read-only regions, mirroring, memory mapped devices or logging.
+pub trait Bus {
+    /// How many bytes the address space has, addresses run from 0 to `size() - 1`
+    fn size(&self) -> usize;
+
+    /// Reads a byte without side effects, for debuggers, crash reports and idle detection
+    fn peek(&self, address: u16) -> u8;
+
+    /// Reads a byte for the program, which memory mapped devices may react to
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Copies `bytes` in from `start` up, e.g. to load a ROM
+    fn load(&mut self, start: u16, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.write(start.wrapping_add(offset as u16), *byte);
+        }
+    }
+}
+
+/// Flat RAM. Addresses past its end wrap around to the start.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ram {
+    bytes: Vec<u8>,
+}
+
+impl Ram {
+    /// `size` bytes of zeroed memory
+    pub fn new(size: usize) -> Self {
+        Ram {
+            bytes: vec![0; size],
+        }
+    }
+}
+
+impl Default for Ram {
+    /// The 4 KiB every CHIP-8 machine has
+    fn default() -> Self {
+        Ram::new(4096)
+    }
+}
+
+impl Bus for Ram {
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bytes[address as usize % self.bytes.len()]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let len = self.bytes.len();
+        self.bytes[address as usize % len] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    /// 1 KiB of RAM repeated through the address space, read-only below 0x200 and
+    /// counting the writes it gets
+    struct Mirrored {
+        ram: Ram,
+        writes: usize,
+    }
+
+    impl Bus for Mirrored {
+        fn size(&self) -> usize {
+            4096
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.ram.peek(address % 1024)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.writes += 1;
+            if address % 1024 >= 0x200 {
+                self.ram.write(address % 1024, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_bus() {
+        let mut cpu = CPU::new();
+        cpu.bus = Box::new(Mirrored {
+            ram: Ram::new(1024),
+            writes: 0,
+        });
+        // 0x200: A600 (I = 0x600, a mirror of 0x200), 0x202: F055, 0x204: 1204
+        cpu.bus.load(0x200, &[0xA6, 0x00, 0xF0, 0x55, 0x12, 0x04]);
+        cpu.registers[0] = 0xAB;
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.peek(0x200), 0xAB);
+
+        // The program overwrote its own first instruction through the mirror
+        cpu.step();
+        assert_eq!(cpu.opcode_at(0x200), Some(0xAB00));
+
+        // Writes to the read-only area are dropped
+        cpu.bus.write(0x100, 1);
+        assert_eq!(cpu.bus.peek(0x100), 0);
+    }
+}
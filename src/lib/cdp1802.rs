@@ -0,0 +1,420 @@
+/// What an RCA CDP1802 is wired to: memory, the N lines selecting an I/O device, the four
+/// EF flag inputs and the Q output
+pub trait Board {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    /// `OUT n`, with the byte the CPU put on the bus
+    fn output(&mut self, port: u8, value: u8);
+
+    /// `INP n`, returning the byte the device puts on the bus
+    fn input(&mut self, port: u8) -> u8;
+
+    /// Whether the flag input EF1 to EF4 is asserted
+    fn flag(&self, flag: u8) -> bool;
+
+    /// Called whenever the Q output changes
+    fn set_q(&mut self, _q: bool) {}
+}
+
+/// The RCA CDP1802 COSMAC microprocessor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cdp1802 {
+    /// The sixteen 16 bit scratchpad registers R0 to RF
+    pub r: [u16; 16],
+
+    /// Which register is the program counter
+    pub p: u8,
+
+    /// Which register points at data for the ALU and I/O instructions
+    pub x: u8,
+
+    /// The accumulator
+    pub d: u8,
+
+    /// The carry flag, set on add carry and cleared on subtract borrow
+    pub df: bool,
+
+    /// X and P as they were when the last interrupt was taken
+    pub t: u8,
+
+    /// Whether interrupts are enabled
+    pub ie: bool,
+
+    pub q: bool,
+
+    /// Set by `IDL` until the next interrupt or DMA
+    pub idle: bool,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    /// A CPU in its reset state, fetching from R0 = 0 with interrupts enabled
+    pub fn new() -> Self {
+        Cdp1802 {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+
+    /// Takes an interrupt if they are enabled: saves X and P in T, then continues at R1 with
+    /// X = 2 and interrupts disabled. Returns the machine cycles it took.
+    pub fn interrupt(&mut self) -> u32 {
+        if !self.ie {
+            return 0;
+        }
+
+        self.t = self.x << 4 | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        1
+    }
+
+    /// One DMA out cycle, returning the byte at R0 and advancing R0
+    pub fn dma_out(&mut self, board: &mut impl Board) -> u8 {
+        let value = board.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    /// Fetches and executes one instruction, returning the machine cycles it took
+    pub fn step(&mut self, board: &mut impl Board) -> u32 {
+        if self.idle {
+            return 1;
+        }
+
+        let opcode = self.fetch(board);
+        let n = (opcode & 0xF) as usize;
+
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = board.read(self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(&*board, n);
+                self.short_branch(board, taken);
+            }
+            0x4 => {
+                self.d = board.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => board.write(self.r[n], self.d),
+            0x6 => self.io(board, n as u8),
+            0x7 => self.control(board, n as u8),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16,
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(board, n as u8);
+                return 3;
+            }
+            0xD => self.p = n as u8,
+            0xE => self.x = n as u8,
+            _ => self.alu(board, n as u8),
+        }
+
+        2
+    }
+
+    /// Reads the byte at R(P) and advances it
+    fn fetch(&mut self, board: &mut impl Board) -> u8 {
+        let pc = &mut self.r[self.p as usize];
+        let value = board.read(*pc);
+        *pc = pc.wrapping_add(1);
+        value
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn set_q(&mut self, board: &mut impl Board, q: bool) {
+        self.q = q;
+        board.set_q(q);
+    }
+
+    /// The condition tested by the branch and skip instructions ending in `n`, inverted
+    /// for the upper half
+    fn condition(&self, board: &impl Board, n: usize) -> bool {
+        let condition = match n & 7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => board.flag(flag as u8 - 3),
+        };
+
+        condition != (n >= 8)
+    }
+
+    /// Jumps within the current page to the byte following the opcode, or skips over it
+    fn short_branch(&mut self, board: &mut impl Board, taken: bool) {
+        let pc = self.r[self.p as usize];
+        let target = board.read(pc);
+
+        self.r[self.p as usize] = if taken {
+            pc & 0xFF00 | target as u16
+        } else {
+            pc.wrapping_add(1)
+        };
+    }
+
+    /// The Cx long branches, long skips and `NOP`
+    fn long_branch(&mut self, board: &mut impl Board, n: u8) {
+        let pc = self.r[self.p as usize];
+
+        // C4 to C7 and CC to CF skip two bytes, with their own conditions
+        let (is_skip, taken) = match n {
+            0x4 => (true, false),
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            0xC => (true, self.ie),
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            0xF => (true, self.df),
+            n => (false, self.condition(&*board, n as usize)),
+        };
+
+        self.r[self.p as usize] = match (is_skip, taken) {
+            (false, true) => u16::from_be_bytes([board.read(pc), board.read(pc.wrapping_add(1))]),
+            (true, false) => pc,
+            _ => pc.wrapping_add(2),
+        };
+    }
+
+    /// `OUT n` for 61 to 67 and `INP n` for 69 to 6F, 60 being `IRX`
+    fn io(&mut self, board: &mut impl Board, n: u8) {
+        let rx = self.rx();
+        match n {
+            0 => self.r[self.x as usize] = rx.wrapping_add(1),
+            1..=7 => {
+                let value = board.read(rx);
+                board.output(n, value);
+                self.r[self.x as usize] = rx.wrapping_add(1);
+            }
+            // 68 does nothing on the 1802
+            8 => {}
+            _ => {
+                let value = board.input(n - 8);
+                board.write(rx, value);
+                self.d = value;
+            }
+        }
+    }
+
+    /// The 7x group: returns, arithmetic with carry, Q and saving T
+    fn control(&mut self, board: &mut impl Board, n: u8) {
+        let (x, rx) = (self.x as usize, self.rx());
+
+        match n {
+            // RET and DIS
+            0x0 | 0x1 => {
+                let value = board.read(rx);
+                self.r[x] = rx.wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = n == 0x0;
+            }
+            // LDXA
+            0x2 => {
+                self.d = board.read(rx);
+                self.r[x] = rx.wrapping_add(1);
+            }
+            // STXD
+            0x3 => {
+                board.write(rx, self.d);
+                self.r[x] = rx.wrapping_sub(1);
+            }
+            // ADC, SDB and SMB
+            0x4 => self.add(board.read(rx), self.df),
+            0x5 => self.subtract(board.read(rx), self.d, !self.df),
+            0x7 => self.subtract(self.d, board.read(rx), !self.df),
+            // SHRC
+            0x6 => {
+                let carry = self.d & 1 == 1;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            // SAV
+            0x8 => board.write(rx, self.t),
+            // MARK
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                board.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.set_q(board, false),
+            0xB => self.set_q(board, true),
+            // ADCI, SDBI and SMBI
+            0xC => {
+                let value = self.fetch(board);
+                self.add(value, self.df);
+            }
+            0xD => {
+                let value = self.fetch(board);
+                self.subtract(value, self.d, !self.df);
+            }
+            0xF => {
+                let value = self.fetch(board);
+                self.subtract(self.d, value, !self.df);
+            }
+            // SHLC
+            _ => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+        }
+    }
+
+    /// The Fx group: logic and arithmetic on M(R(X)), or on an immediate byte for F8 to
+    /// FF, and the plain shifts
+    fn alu(&mut self, board: &mut impl Board, n: u8) {
+        match n {
+            0x6 => {
+                self.df = self.d & 1 == 1;
+                self.d >>= 1;
+                return;
+            }
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+                return;
+            }
+            _ => {}
+        }
+
+        let value = if n < 8 {
+            board.read(self.rx())
+        } else {
+            self.fetch(board)
+        };
+
+        match n & 7 {
+            0 => self.d = value,
+            1 => self.d |= value,
+            2 => self.d &= value,
+            3 => self.d ^= value,
+            4 => self.add(value, false),
+            5 => self.subtract(value, self.d, false),
+            _ => self.subtract(self.d, value, false),
+        }
+    }
+
+    /// D = D + `value` + `carry`, DF set on carry out
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// D = `a` - `b` - `borrow`, DF cleared on borrow
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        let difference = a as i16 - b as i16 - borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat 64 KiB of memory and nothing else
+    struct Ram(Vec<u8>);
+
+    impl Board for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0[address as usize] = value;
+        }
+
+        fn output(&mut self, _port: u8, _value: u8) {}
+
+        fn input(&mut self, _port: u8) -> u8 {
+            0
+        }
+
+        fn flag(&self, _flag: u8) -> bool {
+            false
+        }
+    }
+
+    fn ram(program: &[u8]) -> Ram {
+        let mut ram = vec![0; 0x10000];
+        ram[..program.len()].copy_from_slice(program);
+        Ram(ram)
+    }
+
+    #[test]
+    fn test_arithmetic_and_branches() {
+        let mut board = ram(&[
+            0xF8, 0x05, // LDI 5
+            0xFC, 0xFE, // ADI 0xFE, carries
+            0x33, 0x08, // BDF 0x08
+            0xF8, 0x00, // LDI 0, skipped
+            0xFF, 0x04, // SMI 4, borrows
+            0x3B, 0x0D, // BNF 0x0D
+            0x00, //
+            0x7E, // SHLC
+            0xC0, 0x00, 0x20, // LBR 0x0020
+        ]);
+        board.0[0x20] = 0x00; // IDL
+
+        let mut cpu = Cdp1802::new();
+        let cycles: Vec<u32> = (0..8).map(|_| cpu.step(&mut board)).collect();
+
+        assert_eq!(cycles, [2, 2, 2, 2, 2, 2, 3, 2]);
+        assert_eq!(cpu.d, 0xFE);
+        assert!(cpu.df);
+        assert_eq!(cpu.r[0], 0x21);
+        assert!(cpu.idle);
+    }
+
+    #[test]
+    fn test_interrupt_and_return() {
+        let mut board = ram(&[0x30, 0x00]); // BR 0x00
+                                            // The routine at 0x40 just returns
+        board.0[0x40] = 0x70;
+
+        let mut cpu = Cdp1802::new();
+        cpu.x = 3;
+        cpu.r[1] = 0x40;
+        cpu.r[2] = 0x80;
+
+        assert_eq!(cpu.interrupt(), 1);
+        assert_eq!((cpu.p, cpu.x, cpu.t), (1, 2, 0x30));
+        assert!(!cpu.ie);
+        assert_eq!(cpu.interrupt(), 0);
+
+        // What the routine would have saved with SAV
+        board.0[0x80] = cpu.t;
+        cpu.step(&mut board);
+        assert_eq!((cpu.p, cpu.x), (0, 3));
+        assert_eq!(cpu.r[2], 0x81);
+        assert!(cpu.ie);
+    }
+}
@@ -1,4 +1,29 @@
-const USE_NEW_SHIFTING_CONVENTIONS: bool = false;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::bus::{Bus, Ram};
+use super::crash::HISTORY_LEN;
+use super::extension::OpcodeExtension;
+use super::framebuffer::Framebuffer;
+use super::halt::RunStatus;
+use super::limits::OutputBudget;
+use super::machine::MachineConfig;
+use super::observer::Observer;
+use super::protect::{Diagnostic, Protection};
+use super::quirks::Quirks;
+use super::sink::Sinks;
+use super::stack::StackConfig;
+use super::sys::{builtin_sys_handlers, SysHandler};
+use super::timing::Timing;
+use super::unknown::UnknownOpcodePolicy;
+
+/// How many instructions are executed between two 60 Hz timer ticks by default
+pub const INSTRUCTIONS_PER_FRAME: usize = 11;
+
+/// How long a 60 Hz frame lasts
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 pub const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -19,11 +44,35 @@ pub const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The SUPER-CHIP 8x10 font, with the A to F glyphs Octo added
+pub const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 /// A struct representing the CHIP-8 CPU and RAM
 #[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
-    /// 4K of memory for the CHIP-8
-    pub mem: [u8; 4096],
+    /// The memory size and where the ROM and font go
+    pub machine: MachineConfig,
+
+    /// The memory every instruction fetch and data access goes through, 4K of [`Ram`]
+    /// unless replaced
+    pub bus: Box<dyn Bus>,
 
     /// The program counter
     pub pc: u16,
@@ -31,14 +80,18 @@ pub struct CPU {
     /// The 'I' register to store memory addresses
     pub i_reg: u16,
 
-    /// The stack for the CHIP-8
-    pub stack: [u16; 16],
+    /// Return addresses of the calls that haven't returned yet, outermost first. Empty
+    /// when `stack_config` keeps the stack in memory instead.
+    pub stack: Vec<u16>,
 
-    /// The registers for the CPU
+    /// The registers V0 to VF, VF doubles as the flag register
     pub registers: [u8; 16],
 
-    /// The stack pointer
-    pub sp: u8,
+    /// The number of return addresses on the stack
+    pub sp: usize,
+
+    /// How deep the stack goes and where it is kept
+    pub stack_config: StackConfig,
 
     /// The delay timer
     pub delay_timer: u8,
@@ -46,266 +99,448 @@ pub struct CPU {
     /// The sound timer
     pub sound_timer: u8,
 
-    /// The VF register
-    pub vf: u8,
-
     /// The display buffer
-    pub buf: [u8; 2048],
+    pub buf: Framebuffer,
+
+    /// Whether the screen changed since the last vblank
+    pub screen_dirty: bool,
+
+    /// Whether a changed frame is waiting to be picked up with [`CPU::take_frame`]
+    pub pending_frame: bool,
+
+    /// Whether Dxyn stalled the CPU until the next vblank, see [`Quirks::vblank`]
+    pub waiting_for_vblank: bool,
+
+    /// Which of the 16 keys on the hex keypad are held down
+    pub keys: [bool; 16],
+
+    /// Which interpreter's behaviour to follow where they disagree
+    pub quirks: Quirks,
+
+    /// Whether the program is still making progress, see [`RunStatus`]
+    pub status: RunStatus,
+
+    /// State fingerprints at the end of recent frames, for idle detection
+    pub recent_fingerprints: VecDeque<u64>,
+
+    /// How many times an instruction changed a byte of memory, which idle detection
+    /// fingerprints instead of the whole of memory
+    pub memory_changes: u64,
+
+    /// Receivers of every finished frame, see [`Sinks`]
+    pub sinks: Sinks,
+
+    /// The address and opcode of recently executed instructions, oldest first
+    pub history: VecDeque<(u16, u16)>,
+
+    /// How many instructions `history` keeps
+    pub history_len: usize,
+
+    /// A CRC-32 of the loaded ROM, if one was loaded
+    pub rom_hash: Option<u32>,
+
+    /// Counts the bytes written by outputs that are wrapped with it, see [`OutputBudget`]
+    pub output: OutputBudget,
+
+    /// How many instructions run per frame, see [`Timing`]
+    pub timing: Timing,
+
+    /// Machine cycles left in the current frame under [`Timing::Vip`], negative when the
+    /// last instruction ran over into the next frame
+    pub cycle_budget: i64,
+
+    /// Handlers standing in for the machine code subroutines `0nnn` calls, by address
+    pub sys_handlers: HashMap<u16, SysHandler>,
+
+    /// Handlers for custom opcodes, see [`CPU::register_opcode`]
+    pub extensions: Vec<OpcodeExtension>,
 
-    // Variables for helping with internals, not meant for instruction use.
-    pub last_st_write: u128,
-    pub last_dt_write: u128,
+    /// What to do with opcodes nothing handles
+    pub unknown_opcodes: UnknownOpcodePolicy,
+
+    /// Watches the program run, see [`Observer`]
+    pub observer: Option<Box<dyn Observer>>,
+
+    /// Bytes of ROM loaded at the load address
+    pub rom_size: usize,
+
+    /// Whether to check for suspicious memory accesses, see [`Protection`]
+    pub protection: Protection,
+
+    /// The suspicious memory accesses found so far, at most
+    /// [`MAX_DIAGNOSTICS`](super::protect::MAX_DIAGNOSTICS)
+    pub diagnostics: Vec<Diagnostic>,
+
+    /// Addresses past the ROM the program stored something at, which are fine to read
+    pub written_past_rom: HashSet<u16>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
     /// Initiate a new instance of the CPU struct
-
     #[allow(dead_code)]
     pub fn new() -> Self {
-        let mut mem: [u8; 4096] = [0; 4096];
+        let machine = MachineConfig::default();
+        let mut bus = Ram::new(machine.memory_size);
 
         // Write the font to mem
-        mem[0x050..(0x09F + 1)].copy_from_slice(&FONT[..]);
+        machine.font.load(&mut bus, machine.font_address);
 
         CPU {
             registers: [0; 16],
-            pc: 0x200,
+            pc: machine.load_address,
             sp: 0,
-            mem,
-            stack: [0; 16],
+            machine,
+            bus: Box::new(bus),
+            stack: Vec::new(),
+            stack_config: StackConfig::default(),
             i_reg: 0x200,
-            delay_timer: 255,
-            sound_timer: 255,
-            vf: 0,
-            buf: [0; 2048],
-            last_st_write: 0,
-            last_dt_write: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            buf: Framebuffer::new(),
+            screen_dirty: false,
+            pending_frame: false,
+            waiting_for_vblank: false,
+            keys: [false; 16],
+            quirks: Quirks::default(),
+            status: RunStatus::Running,
+            recent_fingerprints: VecDeque::new(),
+            memory_changes: 0,
+            sinks: Sinks::default(),
+            history: VecDeque::new(),
+            history_len: HISTORY_LEN,
+            rom_hash: None,
+            output: OutputBudget::default(),
+            timing: Timing::default(),
+            cycle_budget: 0,
+            sys_handlers: builtin_sys_handlers(),
+            extensions: Vec::new(),
+            unknown_opcodes: UnknownOpcodePolicy::default(),
+            observer: None,
+            rom_size: 0,
+            protection: Protection::default(),
+            diagnostics: Vec::new(),
+            written_past_rom: HashSet::new(),
         }
     }
 
+    /// A CPU for the default machine with `program` loaded at 0x200. Panics if it doesn't
+    /// fit, see [`CPU::with_machine`] for a fallible version.
     pub fn new_with_memory(program_memory: &[u8]) -> Self {
-        let mut mem: [u8; 4096] = [0; 4096];
-        // Write the font to mem
-        mem[0x050..(0x09F + 1)].copy_from_slice(&FONT[..]);
-
-        // Write the program memory to mem
-        mem[0x200..(program_memory.len() + 0x200)].copy_from_slice(program_memory);
-
-        CPU {
-            registers: [0; 16],
-            pc: 0x200,
-            sp: 0,
-            mem,
-            stack: [0; 16],
-            i_reg: 0x200,
-            delay_timer: 255,
-            sound_timer: 255,
-            vf: 0,
-            buf: [0; 2048],
-            last_st_write: 0,
-            last_dt_write: 0,
-        }
+        CPU::with_machine(MachineConfig::default(), program_memory)
+            .unwrap_or_else(|err| panic!("{err}"))
     }
 
     /// Decodes two bytes into 4 seperate nibbles
     pub fn decode(&self, upper_byte: u8, lower_byte: u8) -> (u8, u8, u8, u8) {
-        let upper_high = ((upper_byte & 0xF0) >> 4) as u8;
-        let upper_low = (upper_byte & 0x0F) as u8;
+        let upper_high = (upper_byte & 0xF0) >> 4;
+        let upper_low = upper_byte & 0x0F;
 
-        let lower_high = ((lower_byte & 0xF0) >> 4) as u8;
-        let lower_low = (lower_byte & 0x0F) as u8;
+        let lower_high = (lower_byte & 0xF0) >> 4;
+        let lower_low = lower_byte & 0x0F;
 
         (upper_high, upper_low, lower_high, lower_low)
     }
 
-    /// Runs the CHIP-8
-    pub fn run(&mut self) {
+    /// Runs the CHIP-8 in real time, printing the screen whenever a frame changed it.
+    /// Nothing presses keys, so once the program halts, faults or gets stuck it stays
+    /// that way: the run stops and returns the status, after printing a crash report to
+    /// stderr for a fault.
+    pub fn run(&mut self) -> RunStatus {
         loop {
-            let instruction =
-                self.decode(self.mem[self.pc as usize], self.mem[self.pc as usize + 1]);
-            self.pc += 2;
-            match instruction {
-                // 0x00E0 - clr
-                (0x0, 0x0, 0xE, 0x0) => {
-                    self.cls00e0();
-                }
-                // 0x1nnn - jp
-                (0x1, nnn_a, nnn_b, nnn_c) => {
-                    let addr = self.to_nnn(nnn_a, nnn_b, nnn_c);
-                    self.jp1nnn(addr);
-                }
+            let started = Instant::now();
 
-                // 0x6xnn - set
-                (0x6, x, upper_nibble, lower_nibble) => {
-                    let nn = (upper_nibble << 4) | lower_nibble;
-                    self.set6xnn(x, nn);
-                }
+            self.run_frame();
+            if self.take_frame().is_some() {
+                self.update();
+            }
 
-                // 0x7Xnn - add
-                (0x7, x, upper_nibble, lower_nibble) => {
-                    let nn = (upper_nibble << 4) | lower_nibble;
-                    self.add7xnn(x, nn);
-                }
+            if let RunStatus::Faulted { pc, fault } = self.status {
+                // Nowhere left to report a failure to write the report
+                let _ = self.write_crash_report(&mut io::stderr().lock(), pc, &fault.to_string());
+            }
+            if self.status.is_stuck() {
+                return self.status;
+            }
 
-                // 0xAnnn - set
-                (0xA, nnn_a, nnn_b, nnn_c) => {
-                    let nn = self.to_nnn(nnn_a, nnn_b, nnn_c);
+            thread::sleep(FRAME_DURATION.saturating_sub(started.elapsed()));
+        }
+    }
 
-                    self.setannn(nn);
-                }
+    /// Runs one frame worth of instructions and then ticks the timers
+    pub fn run_frame(&mut self) {
+        self.run_frame_instructions(|cpu| {
+            cpu.step();
+            None::<()>
+        });
+        self.end_frame();
+    }
 
-                // 0xDxyn - draw
-                (0xD, x, y, n) => {
-                    self.drwdxyn(x, y, n);
-                }
+    /// Everything that happens at the 60 Hz vblank after a frame's instructions ran
+    pub fn end_frame(&mut self) {
+        self.tick_timers();
+        self.vblank();
+        self.check_idle();
+        self.sinks.present(&self.buf, self.sound_timer > 0);
 
-                // 0x2nnn - call
-                (0x2, nnn_a, nnn_b, nnn_c) => {
-                    let addr = self.to_nnn(nnn_a, nnn_b, nnn_c);
-                    self.call2nnn(addr);
-                }
+        if let Some(observer) = &mut self.observer {
+            observer.on_frame(&self.buf);
+        }
+    }
 
-                // 0x00EE - return
-                (0x0, 0x0, 0xE, 0xE) => {
-                    self.ret00ee();
-                }
+    /// Decrements the delay and sound timers, called once per 60 Hz frame
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.set_sound_timer(self.sound_timer.saturating_sub(1));
+    }
 
-                // 0x3xnn - se
-                (0x3, x, upper_nibble, lower_nibble) => {
-                    let nn = (upper_nibble << 4) | lower_nibble;
-                    self.se3xnn(x, nn);
-                }
+    /// Fetches, decodes and executes a single instruction, returning the status it left
+    /// the program in. An instruction that can't be executed returns
+    /// [`RunStatus::Faulted`] instead of panicking.
+    pub fn step(&mut self) -> RunStatus {
+        if self.waiting_for_vblank {
+            return self.status;
+        }
 
-                // 0x4xnn - sne
-                (0x4, x, upper_nibble, lower_nibble) => {
-                    let nn = (upper_nibble << 4) | lower_nibble;
-                    self.sne4xnn(x, nn);
-                }
+        let next = self.wrap_address(self.pc as usize + 1);
+        let (high, low) = (self.bus.read(self.pc), self.bus.read(next));
+        let instruction = self.decode(high, low);
+        self.record_history();
+        if let Some(observer) = &mut self.observer {
+            observer.on_instruction(self.pc, u16::from_be_bytes([high, low]));
+        }
+        self.advance_pc();
+        self.status = RunStatus::Running;
+        match instruction {
+            // 0x00E0 - clr
+            (0x0, 0x0, 0xE, 0x0) => {
+                self.cls00e0();
+            }
+            // 0x1nnn - jp
+            (0x1, nnn_a, nnn_b, nnn_c) => {
+                let addr = self.to_nnn(nnn_a, nnn_b, nnn_c);
+                self.jp1nnn(addr);
+            }
 
-                // 0x5xy0 - se
-                (0x5, x, y, 0x0) => {
-                    self.se5xy0(x, y);
-                }
+            // 0x6xnn - set
+            (0x6, x, upper_nibble, lower_nibble) => {
+                let nn = (upper_nibble << 4) | lower_nibble;
+                self.set6xnn(x, nn);
+            }
 
-                // 0x9xy0 - sne
-                (0x9, x, y, 0x0) => {
-                    self.sne9xy0(x, y);
-                }
+            // 0x7Xnn - add
+            (0x7, x, upper_nibble, lower_nibble) => {
+                let nn = (upper_nibble << 4) | lower_nibble;
+                self.add7xnn(x, nn);
+            }
 
-                // 0x8xy0 - ld
-                (0x8, x, y, 0x0) => {
-                    self.ld8xy0(x, y);
-                }
+            // 0xAnnn - set
+            (0xA, nnn_a, nnn_b, nnn_c) => {
+                let nn = self.to_nnn(nnn_a, nnn_b, nnn_c);
 
-                // 0x8xy1 - bitwise OR
-                (0x8, x, y, 0x1) => {
-                    self.or8xy1(x, y);
-                }
+                self.setannn(nn);
+            }
 
-                // 0x8xy2 - bitwise AND
-                (0x8, x, y, 0x2) => {
-                    self.and8xy2(x, y);
-                }
+            // 0xDxyn - draw
+            (0xD, x, y, n) => {
+                self.drwdxyn(x, y, n);
+            }
 
-                // 0x8xy3 - bitwise XOR
-                (0x8, x, y, 0x3) => {
-                    self.xor8xy3(x, y);
-                }
+            // 0x2nnn - call
+            (0x2, nnn_a, nnn_b, nnn_c) => {
+                let addr = self.to_nnn(nnn_a, nnn_b, nnn_c);
+                self.call2nnn(addr);
+            }
 
-                // 0x8xy4 - ADD
-                (0x8, x, y, 0x4) => {
-                    self.add8xy4(x, y);
-                }
+            // 0x00EE - return
+            (0x0, 0x0, 0xE, 0xE) => {
+                self.ret00ee();
+            }
 
-                // 0x8xy5 - SUB
-                (0x8, x, y, 0x5) => {
-                    self.sub8xy5(x, y);
-                }
+            // 0x3xnn - se
+            (0x3, x, upper_nibble, lower_nibble) => {
+                let nn = (upper_nibble << 4) | lower_nibble;
+                self.se3xnn(x, nn);
+            }
 
-                // 0x8xy7 - SUB
-                (0x8, x, y, 0x7) => {
-                    self.sub8xy7(x, y);
-                }
+            // 0x4xnn - sne
+            (0x4, x, upper_nibble, lower_nibble) => {
+                let nn = (upper_nibble << 4) | lower_nibble;
+                self.sne4xnn(x, nn);
+            }
 
-                // 0x8xy6 - shr
-                (0x8, x, y, 0x6) => match USE_NEW_SHIFTING_CONVENTIONS {
-                    true => {
-                        self.shr8xy6_usex(x, y);
-                    }
-                    false => {
-                        self.shr8xy6_usey(x, y);
-                    }
-                },
-
-                // 0x8xy6 - shr
-                (0x8, x, y, 0xE) => match USE_NEW_SHIFTING_CONVENTIONS {
-                    true => {
-                        self.shl8xye_usex(x, y);
-                    }
-                    false => {
-                        self.shl8xye_usey(x, y);
-                    }
-                },
-
-                // 0xBnnn - jp
-                (0xB, nnn_a, nnn_b, nnn_c) => {
-                    let nnn = self.to_nnn(nnn_a, nnn_b, nnn_c);
-
-                    self.jpbnnn(nnn);
-                }
+            // 0x5xy0 - se
+            (0x5, x, y, 0x0) => {
+                self.se5xy0(x, y);
+            }
 
-                // 0xCxnn - rnd
-                (0xC, x, upper_nibble, lower_nibble) => {
-                    let nn = (upper_nibble << 4) | lower_nibble;
+            // 0x9xy0 - sne
+            (0x9, x, y, 0x0) => {
+                self.sne9xy0(x, y);
+            }
 
-                    self.rndcxnn(x, nn);
-                }
+            // 0x8xy0 - ld
+            (0x8, x, y, 0x0) => {
+                self.ld8xy0(x, y);
+            }
 
-                // 0xFx07 - ldf
-                (0xF, x, 0x0, 0x7) => {
-                    self.ldfx07(x);
-                }
+            // 0x8xy1 - bitwise OR
+            (0x8, x, y, 0x1) => {
+                self.or8xy1(x, y);
+            }
 
-                // 0xFx15 - ld
-                (0xF, x, 0x1, 0x5) => {
-                    self.ldfx15(x);
-                }
+            // 0x8xy2 - bitwise AND
+            (0x8, x, y, 0x2) => {
+                self.and8xy2(x, y);
+            }
 
-                // 0xFx18 - ld
-                (0xF, x, 0x1, 0x8) => {
-                    self.ldfx18(x);
-                }
+            // 0x8xy3 - bitwise XOR
+            (0x8, x, y, 0x3) => {
+                self.xor8xy3(x, y);
+            }
 
-                // 0xFx1E - add
-                (0xF, x, 0x1, 0xE) => {
-                    self.addfx1e(x);
-                }
+            // 0x8xy4 - ADD
+            (0x8, x, y, 0x4) => {
+                self.add8xy4(x, y);
+            }
 
-                // 0xFx29 - ld
-                (0xF, x, 0x2, 0x9) => {
-                    self.ldfx29(x);
-                }
+            // 0x8xy5 - SUB
+            (0x8, x, y, 0x5) => {
+                self.sub8xy5(x, y);
+            }
 
-                // 0xFx33 - ld
-                (0xF, x, 0x3, 0x3) => {
-                    self.ldfx33(x);
-                }
+            // 0x8xy7 - SUB
+            (0x8, x, y, 0x7) => {
+                self.sub8xy7(x, y);
+            }
 
-                // 0xFx55 - ld
-                (0xF, x, 0x5, 0x5) => {
-                    self.ldfx55(x);
+            // 0x8xy6 - shr
+            (0x8, x, y, 0x6) => match self.quirks.shift {
+                true => {
+                    self.shr8xy6_usex(x, y);
                 }
-
-                (0xF, x, 0x6, 0x5) => {
-                    self.ldfx65(x);
+                false => {
+                    self.shr8xy6_usey(x, y);
                 }
+            },
 
-                (a, b, c, d) => {
-                    unimplemented!("0x{:x}{:x}{:x}{:x}", a, b, c, d);
+            // 0x8xy6 - shr
+            (0x8, x, y, 0xE) => match self.quirks.shift {
+                true => {
+                    self.shl8xye_usex(x, y);
+                }
+                false => {
+                    self.shl8xye_usey(x, y);
                 }
+            },
+
+            // 0xBnnn - jp
+            (0xB, nnn_a, nnn_b, nnn_c) => {
+                let nnn = self.to_nnn(nnn_a, nnn_b, nnn_c);
+
+                self.jpbnnn(nnn);
+            }
+
+            // 0xCxnn - rnd
+            (0xC, x, upper_nibble, lower_nibble) => {
+                let nn = (upper_nibble << 4) | lower_nibble;
+
+                self.rndcxnn(x, nn);
+            }
+
+            // 0xEx9E - skp
+            (0xE, x, 0x9, 0xE) => {
+                self.skpex9e(x);
+            }
+
+            // 0xExA1 - sknp
+            (0xE, x, 0xA, 0x1) => {
+                self.skpexa1(x);
+            }
+
+            // 0xFx07 - ldf
+            (0xF, x, 0x0, 0x7) => {
+                self.ldfx07(x);
+            }
+
+            // 0xFx0A - ld
+            (0xF, x, 0x0, 0xA) => {
+                self.ldfx0a(x);
+            }
+
+            // 0xFx15 - ld
+            (0xF, x, 0x1, 0x5) => {
+                self.ldfx15(x);
+            }
+
+            // 0xFx18 - ld
+            (0xF, x, 0x1, 0x8) => {
+                self.ldfx18(x);
+            }
+
+            // 0xFx1E - add
+            (0xF, x, 0x1, 0xE) => {
+                self.addfx1e(x);
+            }
+
+            // 0xFx29 - ld
+            (0xF, x, 0x2, 0x9) => {
+                self.ldfx29(x);
+            }
+
+            // 0xFx30 - ld
+            (0xF, x, 0x3, 0x0) => {
+                self.ldfx30(x);
+            }
+
+            // 0xFx33 - ld
+            (0xF, x, 0x3, 0x3) => {
+                self.ldfx33(x);
+            }
+
+            // 0xFx55 - ld
+            (0xF, x, 0x5, 0x5) => {
+                self.ldfx55(x);
+            }
+
+            (0xF, x, 0x6, 0x5) => {
+                self.ldfx65(x);
+            }
+
+            // 0x0nnn - sys
+            (0x0, nnn_a, nnn_b, nnn_c) => {
+                let addr = self.to_nnn(nnn_a, nnn_b, nnn_c);
+                self.sys0nnn(addr);
+            }
+
+            (a, b, c, d) => {
+                let opcode = (a as u16) << 12 | self.to_nnn(b, c, d);
+                self.unknown_opcode(opcode);
             }
-            self.update();
         }
+
+        self.status
+    }
+
+    /// `address` wrapped around the end of memory, which is where the program counter
+    /// and accesses through I end up when they run past it
+    pub fn wrap_address(&self, address: usize) -> u16 {
+        (address % self.bus.size()) as u16
+    }
+
+    /// Moves the program counter to the next instruction
+    pub fn advance_pc(&mut self) {
+        self.pc = self.wrap_address(self.pc as usize + 2);
+    }
+
+    /// Moves the program counter back to the instruction that just ran, so it runs again
+    pub fn rewind_pc(&mut self) {
+        self.pc = self.wrap_address(self.pc as usize + self.bus.size() - 2);
     }
 
     /// A helper function to convert 8 nibbles into one u16 value
@@ -320,8 +555,7 @@ impl CPU {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::time::{Duration, SystemTime};
+    use crate::halt::Fault;
 
     fn new_cpu() -> CPU {
         CPU::new()
@@ -424,7 +658,7 @@ mod tests {
 
         assert_eq!(cpu.sp, 1);
         assert_eq!(cpu.pc, arbitrary_address);
-        assert_eq!(cpu.stack[cpu.sp as usize], arbitrary_address);
+        assert_eq!(cpu.stack[0], 0x200);
     }
 
     #[test]
@@ -454,6 +688,7 @@ mod tests {
         cpu.ret00ee();
 
         assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.pc, 0x200);
     }
 
     #[test]
@@ -579,7 +814,7 @@ mod tests {
 
         cpu.add8xy4(0, 1);
 
-        assert_eq!(cpu.vf, 0);
+        assert_eq!(cpu.registers[0xF], 0);
         assert_eq!(cpu.registers[0], 20);
     }
 
@@ -595,7 +830,22 @@ mod tests {
 
         cpu.add8xy4(0, 1);
 
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_flag_is_written_after_the_result() {
+        let mut cpu = new_cpu();
+
+        // VF = 200, V1 = 100, 8F14 overflows so the carry replaces the sum in VF
+        cpu.set6xnn(0xF, 200);
+        cpu.set6xnn(1, 100);
+        cpu.add8xy4(0xF, 1);
+        assert_eq!(cpu.registers[0xF], 1);
+
+        // The flag is visible to instructions that read VF like any other register
+        cpu.ld8xy0(2, 0xF);
+        assert_eq!(cpu.registers[2], 1);
     }
 
     #[test]
@@ -610,7 +860,7 @@ mod tests {
 
         cpu.sub8xy5(0, 1);
 
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
         assert_eq!(cpu.registers[0], 25);
     }
 
@@ -626,7 +876,7 @@ mod tests {
 
         cpu.sub8xy5(0, 1);
 
-        assert_eq!(cpu.vf, 0);
+        assert_eq!(cpu.registers[0xF], 0);
         assert_eq!(cpu.registers[0], 231);
     }
 
@@ -642,7 +892,7 @@ mod tests {
 
         cpu.sub8xy7(0, 1);
 
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
         assert_eq!(cpu.registers[0], 25);
     }
 
@@ -658,7 +908,7 @@ mod tests {
 
         cpu.sub8xy7(0, 1);
 
-        assert_eq!(cpu.vf, 0);
+        assert_eq!(cpu.registers[0xF], 0);
         assert_eq!(cpu.registers[0], 231);
     }
 
@@ -673,7 +923,7 @@ mod tests {
         cpu.shr8xy6_usey(0, 1);
 
         assert_eq!(cpu.registers[0], byte >> 1);
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
     }
 
     #[test]
@@ -687,7 +937,7 @@ mod tests {
         cpu.shr8xy6_usey(0, 0);
 
         assert_eq!(cpu.registers[0], byte >> 1);
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
     }
 
     #[test]
@@ -701,7 +951,7 @@ mod tests {
         cpu.shl8xye_usey(0, 1);
 
         assert_eq!(cpu.registers[0], byte << 1);
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
     }
 
     #[test]
@@ -715,7 +965,7 @@ mod tests {
         cpu.shl8xye_usex(0, 0);
 
         assert_eq!(cpu.registers[0], byte << 1);
-        assert_eq!(cpu.vf, 1);
+        assert_eq!(cpu.registers[0xF], 1);
     }
 
     #[test]
@@ -730,16 +980,77 @@ mod tests {
         assert_eq!(cpu.pc, 1279)
     }
 
+    #[test]
+    fn test_skpex9e_and_skpexa1() {
+        let mut cpu = new_cpu();
+
+        cpu.set6xnn(0, 0xA);
+        cpu.keys[0xA] = true;
+        cpu.pc = 500;
+
+        cpu.skpex9e(0);
+        assert_eq!(cpu.pc, 502);
+
+        cpu.skpexa1(0);
+        assert_eq!(cpu.pc, 502);
+    }
+
+    #[test]
+    fn test_ldfx0a() {
+        let mut cpu = new_cpu();
+
+        // Without a key press the instruction repeats
+        cpu.pc = 502;
+        cpu.ldfx0a(0);
+        assert_eq!(cpu.pc, 500);
+
+        cpu.keys[0x7] = true;
+        cpu.pc = 502;
+        cpu.ldfx0a(0);
+        assert_eq!(cpu.pc, 502);
+        assert_eq!(cpu.registers[0], 0x7);
+    }
+
     #[test]
     fn test_delay_instructions() {
         let mut cpu = new_cpu();
 
         cpu.set6xnn(0, 69);
         cpu.ldfx15(0);
-        let time = SystemTime::now();
-        thread::sleep(Duration::from_millis(20));
-        cpu.ldfx07(0);
-        assert_eq!(time.elapsed().unwrap().as_millis() as u8, cpu.registers[0]);
+        for _ in 0..3 {
+            cpu.run_frame();
+        }
+        cpu.ldfx07(1);
+        assert_eq!(cpu.registers[1], 66);
+    }
+
+    #[test]
+    fn test_run_stops_at_a_fault() {
+        // 0x200: 00EE, returning with nothing on the stack
+        let mut cpu = CPU::new_with_memory(&[0x00, 0xEE]);
+
+        assert_eq!(
+            cpu.run(),
+            RunStatus::Faulted {
+                pc: 0x200,
+                fault: Fault::StackUnderflow
+            }
+        );
+    }
+
+    #[test]
+    fn test_addresses_wrap_around_memory() {
+        let mut cpu = new_cpu();
+
+        // An instruction split across the end of memory: 0xFFF: 12, 0x000: 00
+        cpu.bus.write(0xFFF, 0x12);
+        cpu.pc = 0xFFF;
+        assert_eq!(cpu.step(), RunStatus::Running);
+        assert_eq!(cpu.pc, 0x200);
+
+        cpu.registers[0] = 0xFF;
+        cpu.jpbnnn(0xFFF);
+        assert_eq!(cpu.pc, 0x0FE);
     }
 
     #[test]
@@ -793,6 +1104,10 @@ mod tests {
         cpu.set6xnn(0, 0x8);
         cpu.ldfx29(0);
         assert_eq!(cpu.i_reg, 0x50 + 40);
+        // Only the low nibble picks the digit
+        cpu.set6xnn(0, 0x1A);
+        cpu.ldfx29(0);
+        assert_eq!(cpu.i_reg, 0x50 + 50);
     }
 
     #[test]
@@ -804,9 +1119,9 @@ mod tests {
         cpu.i_reg = 1024;
         cpu.ldfx33(0);
 
-        assert_eq!(cpu.mem[1024], 1);
-        assert_eq!(cpu.mem[1025], 2);
-        assert_eq!(cpu.mem[1026], 3);
+        assert_eq!(cpu.bus.peek(1024), 1);
+        assert_eq!(cpu.bus.peek(1025), 2);
+        assert_eq!(cpu.bus.peek(1026), 3);
     }
 
     #[test]
@@ -823,24 +1138,24 @@ mod tests {
         cpu.i_reg = 1024;
         cpu.ldfx55(5);
 
-        assert_eq!(cpu.mem[1024], 0);
-        assert_eq!(cpu.mem[1025], 1);
-        assert_eq!(cpu.mem[1026], 2);
-        assert_eq!(cpu.mem[1027], 3);
-        assert_eq!(cpu.mem[1028], 4);
-        assert_eq!(cpu.mem[1029], 5);
+        assert_eq!(cpu.bus.peek(1024), 0);
+        assert_eq!(cpu.bus.peek(1025), 1);
+        assert_eq!(cpu.bus.peek(1026), 2);
+        assert_eq!(cpu.bus.peek(1027), 3);
+        assert_eq!(cpu.bus.peek(1028), 4);
+        assert_eq!(cpu.bus.peek(1029), 5);
     }
 
     #[test]
     fn test_ldfx65() {
         let mut cpu = new_cpu();
 
-        cpu.mem[1024] = 0;
-        cpu.mem[1025] = 1;
-        cpu.mem[1026] = 2;
-        cpu.mem[1027] = 3;
-        cpu.mem[1028] = 4;
-        cpu.mem[1029] = 5;
+        cpu.bus.write(1024, 0);
+        cpu.bus.write(1025, 1);
+        cpu.bus.write(1026, 2);
+        cpu.bus.write(1027, 3);
+        cpu.bus.write(1028, 4);
+        cpu.bus.write(1029, 5);
 
         cpu.i_reg = 1024;
         cpu.ldfx65(5);
@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::cpu::CPU;
+use super::disasm::disassemble;
+
+/// How many executed instructions a crash report lists by default
+pub const HISTORY_LEN: usize = 32;
+
+/// How many instructions are disassembled on each side of the crashing one
+const DISASSEMBLY_CONTEXT: u16 = 6;
+
+impl CPU {
+    /// Remembers the instruction at `pc`, called by [`CPU::step`] before executing it
+    pub fn record_history(&mut self) {
+        if self.history_len == 0 {
+            return;
+        }
+
+        let opcode = self.opcode_at(self.pc).unwrap_or_default();
+        if self.history.len() >= self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.pc, opcode));
+    }
+
+    /// Writes everything needed to look into a crash at `pc` as plain text: the error, the
+    /// ROM hash, registers and timers, the call stack, the last executed instructions, any
+    /// memory protection diagnostics and the code around `pc`
+    pub fn write_crash_report<W: Write>(
+        &self,
+        out: &mut W,
+        pc: u16,
+        error: &str,
+    ) -> io::Result<()> {
+        writeln!(out, "CHIP-8 crash report")?;
+        writeln!(out)?;
+        writeln!(out, "Error: {error}")?;
+        writeln!(out, "At:    {pc:#05x}")?;
+        match self.rom_hash {
+            Some(hash) => writeln!(out, "ROM:   crc32 {hash:08x}")?,
+            None => writeln!(out, "ROM:   none loaded")?,
+        }
+        writeln!(out)?;
+
+        writeln!(out, "Registers:")?;
+        writeln!(
+            out,
+            "  PC {:#05x}  I {:#05x}  SP {}",
+            self.pc, self.i_reg, self.sp
+        )?;
+        for (half, values) in self.registers.chunks(8).enumerate() {
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {value:#04x}", half * 8 + i))
+                .collect();
+            writeln!(out, "  {}", line.join("  "))?;
+        }
+        writeln!(out, "  DT {}  ST {}", self.delay_timer, self.sound_timer)?;
+        let keys: Vec<String> = (0..16)
+            .filter(|key| self.keys[*key])
+            .map(|key| format!("{key:X}"))
+            .collect();
+        match keys.is_empty() {
+            true => writeln!(out, "  Keys down: none")?,
+            false => writeln!(out, "  Keys down: {}", keys.join(" "))?,
+        }
+        writeln!(out)?;
+
+        writeln!(out, "Call stack, innermost first:")?;
+        let call_stack = self.call_stack();
+        if call_stack.is_empty() {
+            writeln!(out, "  empty")?;
+        }
+        for (depth, address) in call_stack.iter().enumerate() {
+            writeln!(out, "  #{depth} returns to {address:#05x}")?;
+        }
+        writeln!(out)?;
+
+        writeln!(
+            out,
+            "Last {} instructions, oldest first:",
+            self.history.len()
+        )?;
+        for (address, opcode) in &self.history {
+            writeln!(
+                out,
+                "  {address:#05x}  {opcode:04x}  {}",
+                disassemble(*opcode)
+            )?;
+        }
+        writeln!(out)?;
+
+        if !self.diagnostics.is_empty() {
+            writeln!(out, "Memory diagnostics:")?;
+            for diagnostic in &self.diagnostics {
+                writeln!(out, "  {diagnostic}")?;
+            }
+            writeln!(out)?;
+        }
+
+        writeln!(out, "Code around {pc:#05x}:")?;
+        for (address, opcode, assembly) in
+            self.disassemble_around(pc, DISASSEMBLY_CONTEXT, DISASSEMBLY_CONTEXT)
+        {
+            let marker = if address == pc { '>' } else { ' ' };
+            writeln!(out, "{marker} {address:#05x}  {opcode:04x}  {assembly}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves a crash report to a file, see [`CPU::write_crash_report`]
+    pub fn save_crash_report(&self, path: &Path, pc: u16, error: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_crash_report(&mut out, pc, error)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_a_ring_buffer() {
+        // 0x200: 6005, 0x202: 1200, looping forever
+        let mut cpu = CPU::new_with_memory(&[0x60, 0x05, 0x12, 0x00]);
+        cpu.history_len = 3;
+
+        for _ in 0..5 {
+            cpu.step();
+        }
+
+        assert_eq!(
+            cpu.history,
+            [(0x200, 0x6005), (0x202, 0x1200), (0x200, 0x6005)]
+        );
+    }
+
+    #[test]
+    fn test_crash_report() {
+        // 0x200: 2204 (call 0x204), 0x202: 0000, 0x204: 6105, 0x206: 0123
+        let mut cpu = CPU::new_with_memory(&[0x22, 0x04, 0x00, 0x00, 0x61, 0x05, 0x01, 0x23]);
+        cpu.step();
+        cpu.step();
+
+        let mut out = Vec::new();
+        cpu.write_crash_report(&mut out, 0x206, "not implemented: 0x0123")
+            .unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        assert!(report.contains("Error: not implemented: 0x0123"));
+        assert!(report.contains(&format!("crc32 {:08x}", cpu.rom_hash.unwrap())));
+        assert!(report.contains("V1 0x05"));
+        assert!(report.contains("#0 returns to 0x202"));
+        assert!(report.contains("  0x204  6105  LD V1, 0x05"));
+        assert!(report.contains("> 0x206  0123  SYS 0x123"));
+    }
+}
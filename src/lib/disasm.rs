@@ -0,0 +1,121 @@
+use super::cpu::CPU;
+
+/// Turns an opcode into assembly in the usual CHIP-8 mnemonics, e.g. `LD V0, 0x05`.
+/// Opcodes that aren't instructions become `DW` data words.
+pub fn disassemble(opcode: u16) -> String {
+    let nibbles = (
+        (opcode >> 12) as u8,
+        ((opcode >> 8) & 0xF) as u8,
+        ((opcode >> 4) & 0xF) as u8,
+        (opcode & 0xF) as u8,
+    );
+    let nnn = opcode & 0xFFF;
+    let nn = opcode & 0xFF;
+
+    match nibbles {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x0, ..) => format!("SYS {nnn:#05x}"),
+        (0x1, ..) => format!("JP {nnn:#05x}"),
+        (0x2, ..) => format!("CALL {nnn:#05x}"),
+        (0x3, x, ..) => format!("SE V{x:X}, {nn:#04x}"),
+        (0x4, x, ..) => format!("SNE V{x:X}, {nn:#04x}"),
+        (0x5, x, y, 0x0) => format!("SE V{x:X}, V{y:X}"),
+        (0x6, x, ..) => format!("LD V{x:X}, {nn:#04x}"),
+        (0x7, x, ..) => format!("ADD V{x:X}, {nn:#04x}"),
+        (0x8, x, y, 0x0) => format!("LD V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x1) => format!("OR V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x2) => format!("AND V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x3) => format!("XOR V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x4) => format!("ADD V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x5) => format!("SUB V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x6) => format!("SHR V{x:X}, V{y:X}"),
+        (0x8, x, y, 0x7) => format!("SUBN V{x:X}, V{y:X}"),
+        (0x8, x, y, 0xE) => format!("SHL V{x:X}, V{y:X}"),
+        (0x9, x, y, 0x0) => format!("SNE V{x:X}, V{y:X}"),
+        (0xA, ..) => format!("LD I, {nnn:#05x}"),
+        (0xB, ..) => format!("JP V0, {nnn:#05x}"),
+        (0xC, x, ..) => format!("RND V{x:X}, {nn:#04x}"),
+        (0xD, x, y, n) => format!("DRW V{x:X}, V{y:X}, {n}"),
+        (0xE, x, 0x9, 0xE) => format!("SKP V{x:X}"),
+        (0xE, x, 0xA, 0x1) => format!("SKNP V{x:X}"),
+        (0xF, x, 0x0, 0x7) => format!("LD V{x:X}, DT"),
+        (0xF, x, 0x0, 0xA) => format!("LD V{x:X}, K"),
+        (0xF, x, 0x1, 0x5) => format!("LD DT, V{x:X}"),
+        (0xF, x, 0x1, 0x8) => format!("LD ST, V{x:X}"),
+        (0xF, x, 0x1, 0xE) => format!("ADD I, V{x:X}"),
+        (0xF, x, 0x2, 0x9) => format!("LD F, V{x:X}"),
+        (0xF, x, 0x3, 0x0) => format!("LD HF, V{x:X}"),
+        (0xF, x, 0x3, 0x3) => format!("LD B, V{x:X}"),
+        (0xF, x, 0x5, 0x5) => format!("LD [I], V{x:X}"),
+        (0xF, x, 0x6, 0x5) => format!("LD V{x:X}, [I]"),
+        _ => format!("DW {opcode:#06x}"),
+    }
+}
+
+impl CPU {
+    /// The opcode stored at an address, or `None` if it runs past the end of memory
+    pub fn opcode_at(&self, address: u16) -> Option<u16> {
+        if address as usize + 2 > self.bus.size() {
+            return None;
+        }
+
+        Some(u16::from_be_bytes([
+            self.bus.peek(address),
+            self.bus.peek(address + 1),
+        ]))
+    }
+
+    /// Disassembles `before` instructions before `address`, the one at it and `after`
+    /// instructions after it, as (address, opcode, assembly) lines
+    pub fn disassemble_around(
+        &self,
+        address: u16,
+        before: u16,
+        after: u16,
+    ) -> Vec<(u16, u16, String)> {
+        let first = address.saturating_sub(before * 2);
+        let last = address.saturating_add(after * 2);
+
+        (first..=last)
+            .step_by(2)
+            .filter_map(|address| {
+                let opcode = self.opcode_at(address)?;
+                Some((address, opcode, disassemble(opcode)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1228), "JP 0x228");
+        assert_eq!(disassemble(0x6A05), "LD VA, 0x05");
+        assert_eq!(disassemble(0x8AB4), "ADD VA, VB");
+        assert_eq!(disassemble(0xD015), "DRW V0, V1, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0x5121), "DW 0x5121");
+    }
+
+    #[test]
+    fn test_disassemble_around() {
+        let cpu = CPU::new_with_memory(&[0x60, 0x05, 0x12, 0x02]);
+
+        let lines = cpu.disassemble_around(0x202, 1, 1);
+
+        assert_eq!(
+            lines,
+            vec![
+                (0x200, 0x6005, "LD V0, 0x05".to_string()),
+                (0x202, 0x1202, "JP 0x202".to_string()),
+                (0x204, 0x0000, "SYS 0x000".to_string()),
+            ]
+        );
+        assert_eq!(cpu.disassemble_around(0xFFE, 0, 4).len(), 1);
+    }
+}
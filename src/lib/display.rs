@@ -1,69 +1,275 @@
 use super::cpu::CPU;
+use super::framebuffer::Framebuffer;
+use super::png::crc32;
 
-const WIDTH: u8 = 64;
+/// The width of the display in pixels
+pub const WIDTH: u8 = 64;
+
+/// The height of the display in pixels
+pub const HEIGHT: u8 = 32;
+
+/// Prints a screen to the terminal, `#` for lit pixels
+pub fn print_screen(buf: &Framebuffer) {
+    print!("{}", screen_lines(buf));
+}
+
+/// The screen as one line of text per row, `#` for lit pixels and spaces for the rest
+fn screen_lines(buf: &Framebuffer) -> String {
+    let pixels: Vec<bool> = buf.iter().collect();
+    let mut text = String::with_capacity(pixels.len() + HEIGHT as usize);
+
+    for row in pixels.chunks(WIDTH as usize) {
+        text.extend(row.iter().map(|lit| if *lit { '#' } else { ' ' }));
+        text.push('\n');
+    }
+
+    text
+}
 
 impl CPU {
     /// Clears the display
     pub fn clear(&mut self) {
-        self.buf.iter_mut().for_each(|i| *i = 0);
-        self.update();
+        self.buf.clear();
+        self.screen_dirty = true;
     }
 
-    pub fn update(&mut self) {
-        for (i, item) in self.buf.iter_mut().enumerate() {
-            if i != 0 && (i - 1) % (WIDTH as usize) == 0 {
-                println!();
-            }
-
-            if *item == 1 {
-                print!("#");
-            } else {
-                print!(" ");
-            }
+    /// Called at every 60 Hz frame boundary, makes the frame available to hosts if
+    /// anything was drawn since the last one
+    pub fn vblank(&mut self) {
+        if self.screen_dirty {
+            self.screen_dirty = false;
+            self.pending_frame = true;
         }
-        println!();
+        self.waiting_for_vblank = false;
+    }
+
+    /// Whether the screen changed during a frame that has ended and hasn't been taken yet
+    pub fn frame_ready(&self) -> bool {
+        self.pending_frame
+    }
+
+    /// The screen as it was at the last vblank if it changed since the last call, so hosts
+    /// only redraw when there is something new to show
+    pub fn take_frame(&mut self) -> Option<&Framebuffer> {
+        if !self.pending_frame {
+            return None;
+        }
+
+        self.pending_frame = false;
+        Some(&self.buf)
+    }
+
+    pub fn update(&mut self) {
+        print_screen(&self.buf);
+    }
+
+    /// A CRC-32 of the display buffer with one byte per pixel in row order, for
+    /// telling frames apart without storing them
+    pub fn framebuffer_hash(&self) -> u32 {
+        crc32(&self.buf.pixels())
     }
 
+    /// XORs an `n` row sprite from memory at I onto the screen at (Vx, Vy), setting VF if
+    /// any lit pixel was turned off. The starting position always wraps onto the screen,
+    /// pixels past an edge are clipped or wrap around depending on [`Quirks::wrap`].
+    ///
+    /// [`Quirks::wrap`]: super::quirks::Quirks::wrap
     pub fn draw(&mut self, x: u8, y: u8, n: usize) {
-        let x = self.registers[x as usize] % 64;
-        let y = self.registers[y as usize] % 32;
-        self.vf = 0;
-
-        let sprite = &self.mem[(self.i_reg as usize)..(self.i_reg as usize + n as usize)];
-
-        // Convert the coordinates to an index in the frame buffer
-        let mut pos_in_buf = x as usize + (WIDTH as usize * y as usize);
-
-        // Loop through each row in the sprite
-        sprite.iter().for_each(|byte_row| {
-            // This loop pushes the bits one by one to the right for each iteration,
-            // see if it's on or off (using the & 1) and then write it to the frame
-            // buffer
-            for j in (0..8).rev() {
-                // The current bit value, can be 1 or 0
-                let current_bit_value = (byte_row >> j) & 1;
-
-                // Set VF to 1 if the current bit is already on and the updated bit is also on.
-                // Also turn off the bit.
-                if (self.buf[pos_in_buf] == 1) && (current_bit_value == 1) {
-                    self.vf = 1;
-                    self.buf[pos_in_buf] = 0;
-                } else {
-                    // Just write to the display buffer by default.
-                    self.buf[pos_in_buf] = current_bit_value;
-                }
-
-                // If we reached the end of the screen, stop rendering the row
-                if (pos_in_buf % (WIDTH as usize)) == 0 {
-                    break;
-                }
-
-                // Incrementing X coordinate
-                pos_in_buf += 1;
-            }
-
-            // Incrementing Y coordinate
-            pos_in_buf += (WIDTH - 8) as usize;
-        });
+        let x = self.registers[x as usize] as usize % WIDTH as usize;
+        let y = self.registers[y as usize] as usize % HEIGHT as usize;
+
+        // Rows past the end of memory wrap around to the start like I does
+        let mut sprite = [0; 16];
+        let n = n.min(sprite.len());
+        for (row, byte) in sprite.iter_mut().enumerate().take(n) {
+            *byte = self.read_data(self.i_reg as usize + row);
+        }
+
+        let collision = self.buf.draw_sprite(x, y, &sprite[..n], self.quirks.wrap);
+        self.set_flag(collision as u8);
+        self.screen_dirty = true;
+        if let Some(observer) = &mut self.observer {
+            observer.on_draw(x, y, n, collision);
+        }
+        self.waiting_for_vblank = self.quirks.vblank;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws a fully lit 8x`n` sprite at (x, y)
+    fn draw_block(cpu: &mut CPU, x: u8, y: u8, n: usize) {
+        cpu.bus.load(0x300, &vec![0xFF; n]);
+        cpu.setannn(0x300);
+        cpu.set6xnn(0, x);
+        cpu.set6xnn(1, y);
+        cpu.drwdxyn(0, 1, n as u8);
+    }
+
+    fn lit(cpu: &CPU) -> Vec<(usize, usize)> {
+        cpu.buf
+            .iter()
+            .enumerate()
+            .filter(|(_, lit)| *lit)
+            .map(|(i, _)| (i % WIDTH as usize, i / WIDTH as usize))
+            .collect()
+    }
+
+    #[test]
+    fn test_frames_are_presented_at_vblank_when_dirty() {
+        // 0x200: D015 (draw), then 1202 looping without drawing
+        let mut cpu = CPU::new_with_memory(&[0xD0, 0x15, 0x12, 0x02]);
+
+        cpu.step();
+        assert!(cpu.screen_dirty);
+        assert!(!cpu.frame_ready());
+        assert!(cpu.take_frame().is_none());
+
+        cpu.run_frame();
+        assert!(cpu.frame_ready());
+        assert!(cpu.take_frame().is_some());
+        assert!(!cpu.frame_ready());
+
+        // Nothing was drawn in this frame
+        cpu.run_frame();
+        assert!(cpu.take_frame().is_none());
+    }
+
+    #[test]
+    fn test_vblank_quirk_draws_one_sprite_per_frame() {
+        // 0x200: 7001 (V0 += 1), 0x202: D105 (draw), 0x204: 1200
+        let rom = [0x70, 0x01, 0xD1, 0x05, 0x12, 0x00];
+
+        let mut cpu = CPU::new_with_memory(&rom);
+        cpu.run_frame();
+        let without_wait = cpu.registers[0];
+
+        let mut cpu = CPU::new_with_memory(&rom);
+        cpu.quirks.vblank = true;
+        cpu.run_frame();
+        assert_eq!(cpu.registers[0], 1);
+        assert!(!cpu.waiting_for_vblank);
+
+        cpu.run_frame();
+        assert_eq!(cpu.registers[0], 2);
+        assert!(without_wait > 2);
+    }
+
+    #[test]
+    fn test_draw_inside_the_screen() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 10, 5, 2);
+
+        assert_eq!(lit(&cpu).len(), 16);
+        assert_eq!(lit(&cpu)[0], (10, 5));
+        assert_eq!(lit(&cpu)[15], (17, 6));
+        assert_eq!(cpu.registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_draw_xors_and_reports_collisions() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 0, 0, 1);
+
+        // Only the top left pixel of this sprite is lit
+        cpu.bus.write(0x300, 0x80);
+        cpu.drwdxyn(0, 1, 1);
+
+        assert_eq!(lit(&cpu), (1..8).map(|x| (x, 0)).collect::<Vec<_>>());
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_clip_right_edge() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 60, 0, 1);
+
+        assert_eq!(lit(&cpu), vec![(60, 0), (61, 0), (62, 0), (63, 0)]);
+    }
+
+    #[test]
+    fn test_clip_bottom_edge() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 0, 30, 4);
+
+        assert_eq!(lit(&cpu).len(), 16);
+        assert!(lit(&cpu).iter().all(|(_, y)| *y >= 30));
+    }
+
+    #[test]
+    fn test_clip_bottom_right_corner() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 62, 31, 3);
+
+        assert_eq!(lit(&cpu), vec![(62, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn test_left_and_top_edges() {
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 0, 0, 1);
+        assert_eq!(lit(&cpu).first(), Some(&(0, 0)));
+
+        // Starting positions past the edges wrap onto the screen in both modes
+        let mut cpu = CPU::new();
+        draw_block(&mut cpu, 64 + 3, 32 + 2, 1);
+        assert_eq!(lit(&cpu).first(), Some(&(3, 2)));
+    }
+
+    #[test]
+    fn test_wrap_right_and_bottom_edges() {
+        let mut cpu = CPU::new();
+        cpu.quirks.wrap = true;
+        draw_block(&mut cpu, 62, 31, 2);
+
+        assert_eq!(
+            lit(&cpu),
+            vec![
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (4, 0),
+                (5, 0),
+                (62, 0),
+                (63, 0),
+                (0, 31),
+                (1, 31),
+                (2, 31),
+                (3, 31),
+                (4, 31),
+                (5, 31),
+                (62, 31),
+                (63, 31),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sprite_data_wraps_around_memory() {
+        let mut cpu = CPU::new();
+        cpu.bus.write(0xFFF, 0x80);
+        cpu.bus.write(0x000, 0x80);
+        cpu.setannn(0xFFF);
+        cpu.drwdxyn(0, 1, 2);
+
+        assert_eq!(lit(&cpu), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn test_screen_lines_start_each_row_on_a_new_line() {
+        let mut buf = Framebuffer::default();
+        buf.set(0, 0, true);
+        buf.set(63, 0, true);
+        buf.set(1, 1, true);
+
+        let text = screen_lines(&buf);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), HEIGHT as usize);
+        assert!(lines.iter().all(|line| line.len() == WIDTH as usize));
+        assert_eq!(lines[0], format!("#{}#", " ".repeat(62)));
+        assert_eq!(lines[1], format!(" #{}", " ".repeat(62)));
     }
 }
@@ -0,0 +1,122 @@
+use std::io;
+
+use super::cpu::CPU;
+use super::framebuffer::Framebuffer;
+use super::halt::RunStatus;
+use super::input::InputScript;
+use super::limits::{LimitTracker, OutputBudget, StopReason};
+use super::sink::Sinks;
+use super::vip::Vip;
+
+/// What front ends drive: something that runs 60 Hz frames, shows a screen, takes hex
+/// keypad input and hands its frames to sinks. Both the high level [`CPU`] and the
+/// emulated COSMAC [`Vip`] are one, so scripted runs, frame sinks and headless output
+/// work the same for either.
+pub trait Emulator {
+    /// Runs one 60 Hz frame and hands it to the sinks
+    fn run_frame(&mut self);
+
+    /// The screen as of the last frame
+    fn screen(&self) -> &Framebuffer;
+
+    /// Which of the 16 keys on the hex keypad are held down
+    fn keys(&mut self) -> &mut [bool; 16];
+
+    /// Receivers of every finished frame
+    fn sinks(&mut self) -> &mut Sinks;
+
+    /// Counts the bytes written by the outputs of a run
+    fn output(&self) -> &OutputBudget;
+
+    /// Runs one frame unless a limit has been reached. Limits are only checked between
+    /// frames unless the machine can do better.
+    fn run_frame_limited(&mut self, tracker: &mut LimitTracker) -> Option<StopReason> {
+        if let Some(reason) = tracker.check_before_frame(self.output()) {
+            return Some(reason);
+        }
+
+        self.run_frame();
+        tracker.frames += 1;
+        None
+    }
+
+    /// Whether the program can still make progress, if the machine can tell
+    fn status(&self) -> RunStatus {
+        RunStatus::Running
+    }
+
+    /// Whether running more frames after `frame` can still change anything
+    fn is_finished(&self, _frame: usize, _input: &InputScript) -> bool {
+        false
+    }
+
+    /// Runs a number of frames, pressing and releasing keys as the script says
+    fn run_scripted(&mut self, frames: usize, input: &InputScript) {
+        for frame in 1..=frames {
+            input.apply(frame, self.keys());
+            self.run_frame();
+        }
+    }
+
+    /// Finishes and detaches all sinks, returning the first error any of them ran into
+    fn finish_sinks(&mut self) -> io::Result<()> {
+        self.sinks().finish()
+    }
+}
+
+impl Emulator for CPU {
+    fn run_frame(&mut self) {
+        CPU::run_frame(self);
+    }
+
+    fn screen(&self) -> &Framebuffer {
+        &self.buf
+    }
+
+    fn keys(&mut self) -> &mut [bool; 16] {
+        &mut self.keys
+    }
+
+    fn sinks(&mut self) -> &mut Sinks {
+        &mut self.sinks
+    }
+
+    fn output(&self) -> &OutputBudget {
+        &self.output
+    }
+
+    /// Also stops within a frame, at an instruction that faults or once a limit is hit
+    fn run_frame_limited(&mut self, tracker: &mut LimitTracker) -> Option<StopReason> {
+        CPU::run_frame_limited(self, tracker)
+    }
+
+    fn status(&self) -> RunStatus {
+        self.status
+    }
+
+    fn is_finished(&self, frame: usize, input: &InputScript) -> bool {
+        CPU::is_finished(self, frame, input)
+    }
+}
+
+impl Emulator for Vip {
+    fn run_frame(&mut self) {
+        Vip::run_frame(self);
+    }
+
+    fn screen(&self) -> &Framebuffer {
+        &self.buf
+    }
+
+    fn keys(&mut self) -> &mut [bool; 16] {
+        &mut self.board.keys
+    }
+
+    fn sinks(&mut self) -> &mut Sinks {
+        &mut self.sinks
+    }
+
+    fn output(&self) -> &OutputBudget {
+        &self.output
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::cpu::CPU;
+use super::display::{HEIGHT, WIDTH};
+use super::framebuffer::Framebuffer;
+use super::limits::OutputBudget;
+use super::png::{self, PngOptions};
+
+/// The file formats the display buffer can be dumped as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Binary portable bitmap (P4), lit pixels are black
+    Pbm,
+
+    /// Binary portable graymap (P5), lit pixels are white
+    Pgm,
+
+    /// Plain text with one line per row, `#` for lit pixels and `.` for unlit ones
+    Ascii,
+
+    /// Indexed colour PNG with the given scale and palette
+    Png(PngOptions),
+}
+
+impl ImageFormat {
+    /// Picks a format from a file extension or a `--format` value
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "pbm" => Some(ImageFormat::Pbm),
+            "pgm" => Some(ImageFormat::Pgm),
+            "txt" | "ascii" => Some(ImageFormat::Ascii),
+            "png" => Some(ImageFormat::Png(PngOptions::default())),
+            _ => None,
+        }
+    }
+
+    /// Picks a format from the extension of a path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+impl Framebuffer {
+    /// Writes the screen in the given format
+    pub fn write_image<W: Write>(&self, format: ImageFormat, out: &mut W) -> io::Result<()> {
+        match format {
+            ImageFormat::Pbm => self.write_pbm(out),
+            ImageFormat::Pgm => self.write_pgm(out),
+            ImageFormat::Ascii => self.write_ascii(out),
+            ImageFormat::Png(options) => self.write_png(out, &options),
+        }
+    }
+
+    /// Writes the screen to a file, or to stdout if no path is given, counting the bytes
+    /// against `budget`
+    pub fn save(
+        &self,
+        path: Option<&Path>,
+        format: ImageFormat,
+        budget: &OutputBudget,
+    ) -> io::Result<()> {
+        match path {
+            Some(path) => {
+                let mut out = BufWriter::new(budget.wrap(File::create(path)?));
+                self.write_image(format, &mut out)?;
+                out.flush()
+            }
+            None => {
+                let mut out = budget.wrap(io::stdout().lock());
+                self.write_image(format, &mut out)?;
+                out.flush()
+            }
+        }
+    }
+
+    /// Writes the screen as a PNG
+    pub fn write_png<W: Write>(&self, out: &mut W, options: &PngOptions) -> io::Result<()> {
+        png::encode(
+            out,
+            WIDTH as usize,
+            HEIGHT as usize,
+            &self.pixels(),
+            options,
+        )
+    }
+
+    /// Writes the screen as a binary PBM, packing 8 pixels per byte
+    pub fn write_pbm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P4\n{} {}\n", WIDTH, HEIGHT)?;
+
+        // PBM rows are packed most significant bit first, just like the framebuffer's
+        for row in self.rows() {
+            out.write_all(&row.to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the screen as a binary PGM with one byte per pixel
+    pub fn write_pgm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P5\n{} {}\n255\n", WIDTH, HEIGHT)?;
+
+        let pixels: Vec<u8> = self.iter().map(|lit| lit as u8 * 255).collect();
+        out.write_all(&pixels)
+    }
+
+    /// Writes the screen as text, one line per row
+    pub fn write_ascii<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let pixels: Vec<bool> = self.iter().collect();
+        for row in pixels.chunks(WIDTH as usize) {
+            let line: String = row.iter().map(|lit| if *lit { '#' } else { '.' }).collect();
+            writeln!(out, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CPU {
+    /// Saves a PNG screenshot of the display buffer
+    pub fn screenshot(&self, path: &Path, options: &PngOptions) -> io::Result<()> {
+        self.buf
+            .save(Some(path), ImageFormat::Png(*options), &self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pbm() {
+        let mut cpu = CPU::new();
+        cpu.buf.set(0, 0, true);
+        cpu.buf.set(9, 0, true);
+
+        let mut out = Vec::new();
+        cpu.buf.write_pbm(&mut out).unwrap();
+
+        let header = b"P4\n64 32\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out.len(), header.len() + 8 * 32);
+        assert_eq!(out[header.len()], 0b1000_0000);
+        assert_eq!(out[header.len() + 1], 0b0100_0000);
+    }
+
+    #[test]
+    fn test_write_ascii() {
+        let mut cpu = CPU::new();
+        cpu.buf.set(2, 1, true);
+
+        let mut out = Vec::new();
+        cpu.buf.write_ascii(&mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(&lines[1][..4], "..#.");
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(ImageFormat::from_extension("PBM"), Some(ImageFormat::Pbm));
+        assert_eq!(ImageFormat::from_extension("txt"), Some(ImageFormat::Ascii));
+        assert_eq!(
+            ImageFormat::from_extension("png"),
+            Some(ImageFormat::Png(PngOptions::default()))
+        );
+        assert_eq!(ImageFormat::from_extension("bmp"), None);
+    }
+}
@@ -0,0 +1,179 @@
+use super::cpu::CPU;
+
+/// The patterns of the opcodes the CPU implements itself, which extensions can't take
+/// over. `0nnn` isn't one, calls without a sys handler fall through to the extensions.
+pub const BUILTIN_OPCODES: [&str; 35] = [
+    "00E0", "00EE", "1nnn", "2nnn", "3xnn", "4xnn", "5xy0", "6xnn", "7xnn", "8xy0", "8xy1", "8xy2",
+    "8xy3", "8xy4", "8xy5", "8xy6", "8xy7", "8xyE", "9xy0", "Annn", "Bnnn", "Cxnn", "Dxyn", "Ex9E",
+    "ExA1", "Fx07", "Fx0A", "Fx15", "Fx18", "Fx1E", "Fx29", "Fx30", "Fx33", "Fx55", "Fx65",
+];
+
+/// Executes a custom opcode, called with the whole opcode so it can pick out its operands
+pub type OpcodeHandler = fn(&mut CPU, u16);
+
+/// A handler for every opcode matching a pattern
+#[derive(Debug, Clone, Copy)]
+pub struct OpcodeExtension {
+    /// The bits of the opcode that are fixed by the pattern
+    pub mask: u16,
+
+    /// What those bits must be
+    pub value: u16,
+
+    pub handler: OpcodeHandler,
+}
+
+impl OpcodeExtension {
+    /// Parses a pattern like `5xy1` or `Fn75`: four nibbles, each a hex digit that must
+    /// match or one of `x`, `y` and `n` for an operand that can be anything
+    pub fn new(pattern: &str, handler: OpcodeHandler) -> Result<Self, String> {
+        let invalid = || format!("invalid opcode pattern `{pattern}`");
+        if pattern.chars().count() != 4 {
+            return Err(invalid());
+        }
+
+        let (mut mask, mut value) = (0, 0);
+        for nibble in pattern.chars() {
+            mask <<= 4;
+            value <<= 4;
+
+            match nibble.to_digit(16) {
+                Some(digit) => {
+                    mask |= 0xF;
+                    value |= digit as u16;
+                }
+                None if "xyn".contains(nibble.to_ascii_lowercase()) => {}
+                None => return Err(invalid()),
+            }
+        }
+
+        Ok(OpcodeExtension {
+            mask,
+            value,
+            handler,
+        })
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+
+    /// Whether some opcode matches both patterns, which is when they agree on every bit
+    /// both of them fix
+    pub fn overlaps(&self, other: &OpcodeExtension) -> bool {
+        (self.value ^ other.value) & self.mask & other.mask == 0
+    }
+}
+
+impl CPU {
+    /// Runs `handler` for opcodes matching `pattern`, see [`OpcodeExtension::new`]. A
+    /// pattern matching any of the [`BUILTIN_OPCODES`] is an error, as the extension
+    /// would never run for those. The first extension registered wins where patterns
+    /// overlap.
+    pub fn register_opcode(&mut self, pattern: &str, handler: OpcodeHandler) -> Result<(), String> {
+        let extension = OpcodeExtension::new(pattern, handler)?;
+        let builtin = BUILTIN_OPCODES.iter().find(|builtin| {
+            OpcodeExtension::new(builtin, handler).is_ok_and(|builtin| extension.overlaps(&builtin))
+        });
+        if let Some(builtin) = builtin {
+            return Err(format!(
+                "opcode pattern `{pattern}` overlaps the built-in `{builtin}`"
+            ));
+        }
+
+        self.extensions.push(extension);
+        Ok(())
+    }
+
+    /// Runs the extension matching `opcode`, returning whether there was one
+    pub fn run_extension(&mut self, opcode: u16) -> bool {
+        match self
+            .extensions
+            .iter()
+            .find(|extension| extension.matches(opcode))
+        {
+            Some(extension) => {
+                (extension.handler)(self, opcode);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use crate::halt::RunStatus;
+
+    #[test]
+    fn test_parse_patterns() {
+        let extension = OpcodeExtension::new("5xy1", |_, _| {}).unwrap();
+        assert_eq!((extension.mask, extension.value), (0xF00F, 0x5001));
+        assert!(extension.matches(0x5AB1));
+        assert!(!extension.matches(0x5AB0));
+
+        assert!(OpcodeExtension::new("5xy", |_, _| {}).is_err());
+        assert!(OpcodeExtension::new("5xz1", |_, _| {}).is_err());
+    }
+
+    #[test]
+    fn test_extensions_run_before_the_unknown_opcode_policy() {
+        // 0x200: 6003, 0x202: 6105, 0x204: 5011 (V0 *= V1), 0x206: 5012
+        let mut cpu = CPU::new_with_memory(&[0x60, 0x03, 0x61, 0x05, 0x50, 0x11, 0x50, 0x12]);
+        cpu.register_opcode("5xy1", |cpu, opcode| {
+            let x = (opcode >> 8 & 0xF) as usize;
+            let y = (opcode >> 4 & 0xF) as usize;
+            cpu.registers[x] = cpu.registers[x].wrapping_mul(cpu.registers[y]);
+        })
+        .unwrap();
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers[0], 15);
+        assert_eq!(cpu.status, RunStatus::Running);
+
+        cpu.step();
+        assert!(matches!(cpu.status, RunStatus::Faulted { pc: 0x206, .. }));
+    }
+
+    #[test]
+    fn test_builtin_opcodes_match_the_instruction_set() {
+        let builtins: Vec<OpcodeExtension> = BUILTIN_OPCODES
+            .iter()
+            .map(|pattern| OpcodeExtension::new(pattern, |_, _| {}).unwrap())
+            .collect();
+
+        for opcode in 0..=u16::MAX {
+            let text = disassemble(opcode);
+            let implemented = !text.starts_with("DW") && !text.starts_with("SYS");
+            assert_eq!(
+                builtins.iter().any(|builtin| builtin.matches(opcode)),
+                implemented,
+                "{opcode:#06x}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_patterns_overlapping_builtins_are_rejected() {
+        let mut cpu = CPU::new();
+
+        assert_eq!(
+            cpu.register_opcode("Fx65", |_, _| {}),
+            Err("opcode pattern `Fx65` overlaps the built-in `Fx65`".to_string())
+        );
+        assert_eq!(
+            cpu.register_opcode("1234", |_, _| {}),
+            Err("opcode pattern `1234` overlaps the built-in `1nnn`".to_string())
+        );
+        assert!(cpu.register_opcode("00xE", |_, _| {}).is_err());
+        assert!(cpu.register_opcode("8xyn", |_, _| {}).is_err());
+        assert!(cpu.extensions.is_empty());
+
+        assert!(cpu.register_opcode("8xyF", |_, _| {}).is_ok());
+        assert!(cpu.register_opcode("00Fn", |_, _| {}).is_ok());
+    }
+}
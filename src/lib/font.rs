@@ -0,0 +1,230 @@
+use std::str::FromStr;
+
+use super::bus::Bus;
+use super::cpu::{BIG_FONT, FONT};
+
+/// Bytes in a 4x5 font of the sixteen hex digits
+pub const SMALL_FONT_SIZE: usize = 80;
+
+/// Bytes in an 8x10 font of the sixteen hex digits
+pub const BIG_FONT_SIZE: usize = 160;
+
+/// Rows, and so bytes, in each glyph of the small font
+pub const SMALL_GLYPH_HEIGHT: usize = 5;
+
+/// Rows, and so bytes, in each glyph of the big font
+pub const BIG_GLYPH_HEIGHT: usize = 10;
+
+/// The COSMAC VIP interpreter's digits
+const VIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// The DREAM 6800's three pixel wide digits
+const DREAM6800_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The ETI-660's digits, with lower case b and d
+const ETI660_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // b
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // d
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// FISH-N-CHIPS' rounded digits
+const FISH_N_CHIPS_FONT: [u8; 80] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The glyphs Fx29 and Fx30 point I at, stored in memory from the machine's font address
+/// with the big font right after the small one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontSet {
+    /// 4x5 digits 0 to F, 5 bytes each
+    pub small: [u8; SMALL_FONT_SIZE],
+
+    /// 8x10 digits 0 to F, 10 bytes each
+    pub big: [u8; BIG_FONT_SIZE],
+}
+
+impl Default for FontSet {
+    fn default() -> Self {
+        FontSet::chip8()
+    }
+}
+
+impl FontSet {
+    /// The font most modern interpreters use, with the SUPER-CHIP big font
+    pub fn chip8() -> Self {
+        FontSet {
+            small: FONT,
+            big: BIG_FONT,
+        }
+    }
+
+    /// Machines that never had a big font get the SUPER-CHIP one, so Fx30 still shows
+    /// something sensible
+    fn with_small(small: [u8; SMALL_FONT_SIZE]) -> Self {
+        FontSet {
+            small,
+            big: BIG_FONT,
+        }
+    }
+
+    pub fn vip() -> Self {
+        FontSet::with_small(VIP_FONT)
+    }
+
+    pub fn dream6800() -> Self {
+        FontSet::with_small(DREAM6800_FONT)
+    }
+
+    pub fn eti660() -> Self {
+        FontSet::with_small(ETI660_FONT)
+    }
+
+    pub fn fish_n_chips() -> Self {
+        FontSet::with_small(FISH_N_CHIPS_FONT)
+    }
+
+    /// Stores the small font at `address` and the big font right after it
+    pub fn load(&self, bus: &mut dyn Bus, address: u16) {
+        bus.load(address, &self.small);
+        bus.load(address + SMALL_FONT_SIZE as u16, &self.big);
+    }
+
+    /// A font file: 80 bytes of small font, optionally followed by 160 bytes of big font.
+    /// Without one the SUPER-CHIP big font is used.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut font = FontSet::chip8();
+
+        match bytes.len() {
+            SMALL_FONT_SIZE => font.small.copy_from_slice(bytes),
+            len if len == SMALL_FONT_SIZE + BIG_FONT_SIZE => {
+                font.small.copy_from_slice(&bytes[..SMALL_FONT_SIZE]);
+                font.big.copy_from_slice(&bytes[SMALL_FONT_SIZE..]);
+            }
+            len => {
+                return Err(format!(
+                    "a font file has {SMALL_FONT_SIZE} or {} bytes, got {len}",
+                    SMALL_FONT_SIZE + BIG_FONT_SIZE
+                ))
+            }
+        }
+
+        Ok(font)
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    /// Parses `chip8`, `schip`, `vip`, `dream6800`, `eti660` or `fishnchips`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "chip8" | "schip" => Ok(FontSet::chip8()),
+            "vip" => Ok(FontSet::vip()),
+            "dream6800" => Ok(FontSet::dream6800()),
+            "eti660" => Ok(FontSet::eti660()),
+            "fishnchips" => Ok(FontSet::fish_n_chips()),
+            _ => Err(format!("unknown font `{value}`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::machine::MachineConfig;
+
+    #[test]
+    fn test_font_files() {
+        let font = FontSet::from_bytes(&[0x11; 80]).unwrap();
+        assert_eq!(font.small, [0x11; 80]);
+        assert_eq!(font.big, BIG_FONT);
+
+        let font = FontSet::from_bytes(&[0x22; 240]).unwrap();
+        assert_eq!(font.big, [0x22; 160]);
+
+        assert!(FontSet::from_bytes(&[0; 100]).is_err());
+    }
+
+    #[test]
+    fn test_digits_resolve_against_the_configured_font() {
+        let machine = MachineConfig {
+            font: FontSet::vip(),
+            ..MachineConfig::default()
+        };
+        // 0x200: 6101, 0x202: F129, 0x204: F130
+        let mut cpu = CPU::with_machine(machine, &[0x61, 0x01, 0xF1, 0x29, 0xF1, 0x30]).unwrap();
+
+        cpu.step();
+        cpu.step();
+        let glyph: Vec<u8> = (0..5).map(|row| cpu.bus.peek(cpu.i_reg + row)).collect();
+        assert_eq!(glyph, VIP_FONT[5..10]);
+
+        cpu.step();
+        assert_eq!(cpu.i_reg, 0x050 + 80 + 10);
+        assert_eq!(cpu.bus.peek(cpu.i_reg), BIG_FONT[10]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr};
+
+/// An integer that holds one row of pixels, the leftmost pixel in the most significant bit
+pub trait Row:
+    Copy
+    + Eq
+    + Hash
+    + Debug
+    + Default
+    + From<u8>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+{
+    /// The number of pixels in a row
+    const WIDTH: usize;
+
+    /// The number of rows, half the width for both CHIP-8 resolutions
+    const HEIGHT: usize = Self::WIDTH / 2;
+
+    fn rotate_right(self, n: u32) -> Self;
+}
+
+impl Row for u64 {
+    const WIDTH: usize = 64;
+
+    fn rotate_right(self, n: u32) -> Self {
+        u64::rotate_right(self, n)
+    }
+}
+
+impl Row for u128 {
+    const WIDTH: usize = 128;
+
+    fn rotate_right(self, n: u32) -> Self {
+        u128::rotate_right(self, n)
+    }
+}
+
+/// A monochrome screen stored one packed integer per row, `u64` rows for the 64x32
+/// CHIP-8 screen and `u128` rows for the 128x64 SUPER-CHIP one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Framebuffer<R: Row = u64> {
+    rows: Vec<R>,
+}
+
+/// The 128x64 SUPER-CHIP screen
+pub type HiresFramebuffer = Framebuffer<u128>;
+
+impl<R: Row> Default for Framebuffer<R> {
+    fn default() -> Self {
+        Framebuffer {
+            rows: vec![R::default(); R::HEIGHT],
+        }
+    }
+}
+
+impl<R: Row> Framebuffer<R> {
+    /// A blank screen
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn width(&self) -> usize {
+        R::WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        R::HEIGHT
+    }
+
+    /// The bit for column `x` in a row
+    fn mask(x: usize) -> R {
+        R::from(1) << (R::WIDTH - 1 - x) as u32
+    }
+
+    /// Whether the pixel at (x, y) is lit, pixels off the screen are unlit
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < R::WIDTH && y < R::HEIGHT && self.rows[y] & Self::mask(x) != R::default()
+    }
+
+    /// Lights or clears the pixel at (x, y), ignoring pixels off the screen
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        if x >= R::WIDTH || y >= R::HEIGHT {
+            return;
+        }
+
+        if lit {
+            self.rows[y] = self.rows[y] | Self::mask(x);
+        } else {
+            self.rows[y] = self.rows[y] & !Self::mask(x);
+        }
+    }
+
+    /// Turns every pixel off
+    pub fn clear(&mut self) {
+        self.rows.fill(R::default());
+    }
+
+    /// The packed rows from top to bottom
+    pub fn rows(&self) -> &[R] {
+        &self.rows
+    }
+
+    /// Replaces row `y` with packed pixels, ignoring rows off the screen
+    pub fn set_row(&mut self, y: usize, row: R) {
+        if let Some(slot) = self.rows.get_mut(y) {
+            *slot = row;
+        }
+    }
+
+    /// Every pixel in row order, left to right and top to bottom
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|row| (0..R::WIDTH).map(move |x| *row & Self::mask(x) != R::default()))
+    }
+
+    /// One byte per pixel in row order, 1 for lit and 0 for unlit, the palette indices
+    /// the image and video encoders take
+    pub fn pixels(&self) -> Vec<u8> {
+        self.iter().map(|lit| lit as u8).collect()
+    }
+
+    /// XORs an 8 pixel wide sprite onto the screen with its top left corner at (x, y),
+    /// which must be on the screen. Pixels past the right or bottom edge wrap around when
+    /// `wrap` is set and are clipped otherwise. Returns whether a lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], wrap: bool) -> bool {
+        let mut collision = false;
+
+        for (row, byte) in sprite.iter().enumerate() {
+            let mut py = y + row;
+            if py >= R::HEIGHT {
+                if !wrap {
+                    break;
+                }
+                py %= R::HEIGHT;
+            }
+
+            // Line the sprite up with the left edge, then move it over to x
+            let aligned = R::from(*byte) << (R::WIDTH - 8) as u32;
+            let bits = if wrap {
+                aligned.rotate_right(x as u32)
+            } else {
+                aligned >> x as u32
+            };
+
+            collision |= self.rows[py] & bits != R::default();
+            self.rows[py] = self.rows[py] ^ bits;
+        }
+
+        collision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let mut framebuffer = Framebuffer::<u64>::new();
+
+        framebuffer.set(0, 0, true);
+        framebuffer.set(63, 31, true);
+        framebuffer.set(64, 0, true);
+
+        assert!(framebuffer.get(0, 0));
+        assert!(framebuffer.get(63, 31));
+        assert!(!framebuffer.get(1, 0));
+        assert!(!framebuffer.get(64, 0));
+        assert_eq!(framebuffer.rows()[0], 1 << 63);
+        assert_eq!(framebuffer.iter().filter(|lit| *lit).count(), 2);
+
+        framebuffer.set(0, 0, false);
+        assert!(!framebuffer.get(0, 0));
+    }
+
+    #[test]
+    fn test_hires_draw_sprite() {
+        let mut framebuffer = HiresFramebuffer::new();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (128, 64));
+
+        assert!(!framebuffer.draw_sprite(124, 63, &[0xFF, 0xFF], false));
+        assert_eq!(framebuffer.rows()[63], 0xF);
+        assert_eq!(framebuffer.rows()[0], 0);
+
+        assert!(framebuffer.draw_sprite(124, 63, &[0xFF], true));
+        assert_eq!(framebuffer.rows()[63], 0xF << 124);
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::png::{Rgb, DEFAULT_PALETTE};
+
+/// GIF codes are at most 12 bits wide, so the dictionary holds at most 4096 entries
+const MAX_CODES: u16 = 4096;
+
+/// Every frame uses 2 bit palette indices, which is also the smallest code size GIF allows
+const MIN_CODE_SIZE: u8 = 2;
+
+/// Settings for recording a GIF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Every pixel becomes a `scale` x `scale` block
+    pub scale: u32,
+
+    /// The colour for each pixel value
+    pub palette: [Rgb; 4],
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            scale: 8,
+            palette: DEFAULT_PALETTE,
+        }
+    }
+}
+
+/// Collects 60 Hz frames and writes them as a looping animated GIF.
+///
+/// Identical consecutive frames are merged into one image with a longer delay.
+pub struct GifRecorder {
+    width: usize,
+    height: usize,
+    options: GifOptions,
+
+    /// Each distinct frame along with the number of 60 Hz frames it was shown for
+    frames: Vec<(Vec<u8>, usize)>,
+}
+
+impl GifRecorder {
+    pub fn new(width: usize, height: usize, options: GifOptions) -> Self {
+        GifRecorder {
+            width,
+            height,
+            options,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Records one 60 Hz frame of palette indices
+    pub fn capture(&mut self, pixels: &[u8]) {
+        match self.frames.last_mut() {
+            Some((last, shown)) if last.as_slice() == pixels => *shown += 1,
+            _ => self.frames.push((pixels.to_vec(), 1)),
+        }
+    }
+
+    /// The number of images the GIF will contain after merging
+    pub fn image_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Saves the recording to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)?;
+        out.flush()
+    }
+
+    /// Writes the recording as a GIF89a stream
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let scale = self.options.scale.max(1) as usize;
+        // The header only has 16 bits for each dimension
+        let dimension = |pixels: usize| {
+            pixels
+                .checked_mul(scale)
+                .and_then(|scaled| u16::try_from(scaled).ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "a GIF can be at most 65535 pixels wide and high, {}x{} at scale {scale} is too big",
+                            self.width, self.height
+                        ),
+                    )
+                })
+        };
+        let (width, height) = (dimension(self.width)?, dimension(self.height)?);
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&width.to_le_bytes())?;
+        out.write_all(&height.to_le_bytes())?;
+        // A global colour table with 2^(1 + 1) entries, background colour 0, square pixels
+        out.write_all(&[0b1000_0001, 0, 0])?;
+        out.write_all(self.options.palette.as_flattened())?;
+
+        // Loop forever
+        out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        let mut elapsed = 0;
+        for (pixels, shown) in &self.frames {
+            let delay = frame_delay(elapsed, *shown);
+            elapsed += shown;
+
+            // Graphic control extension with the delay in hundredths of a second
+            out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+            out.write_all(&delay.to_le_bytes())?;
+            out.write_all(&[0x00, 0x00])?;
+
+            // Image descriptor covering the whole screen without a local colour table
+            out.write_all(&[0x2C, 0, 0, 0, 0])?;
+            out.write_all(&width.to_le_bytes())?;
+            out.write_all(&height.to_le_bytes())?;
+            out.write_all(&[0x00])?;
+
+            let indices = self.scale(pixels, scale);
+            out.write_all(&[MIN_CODE_SIZE])?;
+            for block in lzw_encode(MIN_CODE_SIZE, &indices).chunks(255) {
+                out.write_all(&[block.len() as u8])?;
+                out.write_all(block)?;
+            }
+            out.write_all(&[0x00])?;
+        }
+
+        out.write_all(&[0x3B])
+    }
+
+    /// Upscales a frame with nearest neighbour sampling
+    fn scale(&self, pixels: &[u8], scale: usize) -> Vec<u8> {
+        let mut indices = Vec::with_capacity(pixels.len() * scale * scale);
+
+        for row in pixels.chunks(self.width) {
+            let scaled_row: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| std::iter::repeat_n((*pixel).min(3), scale))
+                .collect();
+            for _ in 0..scale {
+                indices.extend_from_slice(&scaled_row);
+            }
+        }
+
+        indices
+    }
+}
+
+/// The delay in hundredths of a second for an image shown from 60 Hz frame `start`
+/// for `shown` frames. Rounding the start and end times instead of each delay keeps
+/// the total length of the animation exact.
+pub fn frame_delay(start: usize, shown: usize) -> u16 {
+    let centiseconds = |frame: usize| (frame * 100 + 30) / 60;
+
+    (centiseconds(start + shown) - centiseconds(start)) as u16
+}
+
+/// Compresses palette indices with the variable width LZW flavour GIF uses
+pub fn lzw_encode(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter::default();
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end_code + 1;
+
+    writer.write(clear_code, code_size);
+
+    let mut indices = indices.iter();
+    let Some(first) = indices.next() else {
+        writer.write(end_code, code_size);
+        return writer.finish();
+    };
+
+    let mut prefix = *first as u16;
+    for index in indices {
+        if let Some(code) = dictionary.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+
+        writer.write(prefix, code_size);
+
+        if next_code < MAX_CODES {
+            dictionary.insert((prefix, *index), next_code);
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            next_code += 1;
+        } else {
+            // The dictionary is full, start over with a fresh one
+            writer.write(clear_code, code_size);
+            dictionary.clear();
+            code_size = min_code_size + 1;
+            next_code = end_code + 1;
+        }
+
+        prefix = *index as u16;
+    }
+
+    writer.write(prefix, code_size);
+    writer.write(end_code, code_size);
+    writer.finish()
+}
+
+/// Packs codes least significant bit first, the way GIF expects them
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A straightforward GIF LZW decoder to check the encoder against
+    fn lzw_decode(min_code_size: u8, data: &[u8]) -> Vec<u8> {
+        let clear_code = 1u16 << min_code_size;
+        let end_code = clear_code + 1;
+
+        let mut out = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0u8, data.iter());
+
+        loop {
+            while bits < code_size {
+                buffer |= (*bytes.next().unwrap() as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as u16;
+            buffer >>= code_size;
+            bits -= code_size;
+
+            if code == clear_code {
+                table = (0..clear_code).map(|i| vec![i as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return out;
+            }
+
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => {
+                    let mut entry = previous.clone();
+                    entry.push(previous[0]);
+                    entry
+                }
+                (None, None) => panic!("invalid first code"),
+            };
+
+            if let Some(mut new_entry) = previous.take() {
+                if table.len() < MAX_CODES as usize {
+                    new_entry.push(entry[0]);
+                    table.push(new_entry);
+                    if table.len() == 1 << code_size && code_size < 12 {
+                        code_size += 1;
+                    }
+                }
+            }
+
+            out.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        let simple = vec![0, 0, 1, 1, 0, 0, 1, 1, 1, 1, 0, 0];
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &simple)), simple);
+
+        // Enough varied data to fill the dictionary and force a clear code
+        let mut state = 12345u32;
+        let noisy: Vec<u8> = (0..40_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) % 4) as u8
+            })
+            .collect();
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &noisy)), noisy);
+
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &[])), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        // Three 60 Hz frames last exactly five hundredths of a second
+        let delays: Vec<u16> = (0..3).map(|frame| frame_delay(frame, 1)).collect();
+        assert_eq!(delays.iter().sum::<u16>(), 5);
+
+        assert_eq!(frame_delay(0, 60), 100);
+    }
+
+    #[test]
+    fn test_identical_frames_are_merged() {
+        let mut recorder = GifRecorder::new(2, 1, GifOptions::default());
+
+        recorder.capture(&[0, 1]);
+        recorder.capture(&[0, 1]);
+        recorder.capture(&[1, 1]);
+        recorder.capture(&[0, 1]);
+
+        assert_eq!(recorder.image_count(), 3);
+
+        let mut out = Vec::new();
+        recorder.write(&mut out).unwrap();
+        assert_eq!(&out[..6], b"GIF89a");
+        assert_eq!(out.last(), Some(&0x3B));
+    }
+
+    #[test]
+    fn test_oversized_gifs_are_rejected() {
+        let options = GifOptions {
+            scale: 1024,
+            ..GifOptions::default()
+        };
+        let mut recorder = GifRecorder::new(64, 32, options);
+        recorder.capture(&[0; 64 * 32]);
+
+        let mut out = Vec::new();
+        let err = recorder.write(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use super::cpu::CPU;
+use super::input::InputScript;
+
+/// How many frames back idle detection looks for a repeated state. Loops whose length
+/// does not divide the number of instructions per frame only repeat every few frames.
+const FINGERPRINT_HISTORY: usize = 16;
+
+/// Whether the program can still make progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The program is doing work
+    #[default]
+    Running,
+
+    /// The program jumped to itself with `1nnn`, which is how most ROMs end
+    Halted { pc: u16 },
+
+    /// Fx0A is waiting for a key press
+    WaitingForKey { pc: u16 },
+
+    /// The machine state at the end of a frame repeated an earlier one, so the
+    /// program is going round in a loop that changes nothing
+    Idle { pc: u16 },
+
+    /// The instruction at `pc` can't be executed, running it again faults the same way
+    Faulted { pc: u16, fault: Fault },
+}
+
+/// Why an instruction couldn't be executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// `2nnn` with `depth` return addresses already on a full stack
+    StackOverflow { depth: usize },
+
+    /// `00EE` with nothing on the stack to return to
+    StackUnderflow,
+
+    /// An opcode nothing handles, including `0nnn` calls to routines without a handler
+    UnknownOpcode { opcode: u16 },
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::StackOverflow { depth } => {
+                write!(f, "stack overflow, {depth} calls deep")
+            }
+            Fault::StackUnderflow => {
+                write!(f, "stack underflow, returned with no call to return from")
+            }
+            Fault::UnknownOpcode { opcode } if opcode >> 12 == 0 => {
+                write!(
+                    f,
+                    "no handler for the machine code routine at {opcode:#05x}"
+                )
+            }
+            Fault::UnknownOpcode { opcode } => write!(f, "unknown opcode {opcode:04x}"),
+        }
+    }
+}
+
+impl RunStatus {
+    /// True for every status except [`RunStatus::Running`]
+    pub fn is_stuck(&self) -> bool {
+        *self != RunStatus::Running
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunStatus::Running => write!(f, "running"),
+            RunStatus::Halted { pc } => write!(f, "halted at {pc:#05x}"),
+            RunStatus::WaitingForKey { pc } => write!(f, "waiting for a key at {pc:#05x}"),
+            RunStatus::Idle { pc } => write!(f, "idle at {pc:#05x}"),
+            RunStatus::Faulted { pc, fault } => write!(f, "faulted at {pc:#05x}: {fault}"),
+        }
+    }
+}
+
+impl CPU {
+    /// A hash of everything a program can observe or change, used to spot frames
+    /// where nothing happened. Memory is represented by the number of changes made to it,
+    /// so a loop that keeps rewriting memory with different values never counts as idle.
+    pub fn state_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        self.pc.hash(&mut hasher);
+        self.i_reg.hash(&mut hasher);
+        self.registers.hash(&mut hasher);
+        self.sp.hash(&mut hasher);
+        self.stack.hash(&mut hasher);
+        self.delay_timer.hash(&mut hasher);
+        self.sound_timer.hash(&mut hasher);
+        self.memory_changes.hash(&mut hasher);
+        self.buf.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Marks the CPU idle if the state at the end of this frame was already seen at the
+    /// end of a recent frame. Without new input the program would keep repeating it.
+    pub fn check_idle(&mut self) {
+        let fingerprint = self.state_fingerprint();
+
+        if self.status == RunStatus::Running && self.recent_fingerprints.contains(&fingerprint) {
+            self.status = RunStatus::Idle { pc: self.pc };
+        }
+
+        if self.recent_fingerprints.len() == FINGERPRINT_HISTORY {
+            self.recent_fingerprints.pop_front();
+        }
+        self.recent_fingerprints.push_back(fingerprint);
+    }
+
+    /// Runs up to `frames` frames with scripted input, stopping early once the program
+    /// has halted or is stuck and no more input is coming. Returns the number of frames run.
+    pub fn run_until_halt(&mut self, frames: usize, input: &InputScript) -> usize {
+        for frame in 1..=frames {
+            input.apply(frame, &mut self.keys);
+            self.run_frame();
+
+            if self.is_finished(frame, input) {
+                return frame;
+            }
+        }
+
+        frames
+    }
+
+    /// Whether running more frames after `frame` can still change anything
+    pub fn is_finished(&self, frame: usize, input: &InputScript) -> bool {
+        match self.status {
+            RunStatus::Running => false,
+            RunStatus::Halted { .. } | RunStatus::Faulted { .. } => true,
+            // A key press later on could still wake the program up
+            RunStatus::WaitingForKey { .. } | RunStatus::Idle { .. } => {
+                !input.has_events_after(frame)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_jump_halts() {
+        // 0x200: 6005 (V0 = 5), 0x202: 1202 (jump to itself)
+        let mut cpu = CPU::new_with_memory(&[0x60, 0x05, 0x12, 0x02]);
+
+        let frames = cpu.run_until_halt(100, &InputScript::default());
+
+        assert_eq!(frames, 1);
+        assert_eq!(cpu.status, RunStatus::Halted { pc: 0x202 });
+    }
+
+    #[test]
+    fn test_key_wait_runs_until_input_is_done() {
+        // 0x200: F00A (wait for a key), 0x202: 1202
+        let mut cpu = CPU::new_with_memory(&[0xF0, 0x0A, 0x12, 0x02]);
+        let input: InputScript = "5 press 3".parse().unwrap();
+
+        let frames = cpu.run_until_halt(100, &input);
+
+        assert_eq!(frames, 5);
+        assert_eq!(cpu.registers[0], 3);
+        assert_eq!(cpu.status, RunStatus::Halted { pc: 0x202 });
+
+        let mut cpu = CPU::new_with_memory(&[0xF0, 0x0A, 0x12, 0x02]);
+        assert_eq!(cpu.run_until_halt(100, &InputScript::default()), 1);
+        assert_eq!(cpu.status, RunStatus::WaitingForKey { pc: 0x200 });
+    }
+
+    #[test]
+    fn test_idle_loop() {
+        // A two instruction loop polling key 0: 0x200: E09E, 0x202: 1200
+        let mut cpu = CPU::new_with_memory(&[0xE0, 0x9E, 0x12, 0x00]);
+
+        let frames = cpu.run_until_halt(100, &InputScript::default());
+
+        // The loop is two instructions long and a frame is an odd number of
+        // instructions, so the state only repeats every other frame
+        assert_eq!(frames, 3);
+        assert!(matches!(cpu.status, RunStatus::Idle { .. }));
+    }
+
+    #[test]
+    fn test_rewriting_the_same_memory_is_idle() {
+        // 0x200: A300, 0x202: 6005, 0x204: F055 (store V0 at 0x300), 0x206: 1204
+        let mut cpu = CPU::new_with_memory(&[0xA3, 0x00, 0x60, 0x05, 0xF0, 0x55, 0x12, 0x04]);
+
+        cpu.run_until_halt(100, &InputScript::default());
+
+        assert!(matches!(cpu.status, RunStatus::Idle { .. }));
+        assert_eq!(cpu.memory_changes, 1);
+    }
+}
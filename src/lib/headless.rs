@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use super::display::{HEIGHT, WIDTH};
+use super::emulator::Emulator;
+use super::export::ImageFormat;
+use super::gif::{GifOptions, GifRecorder};
+use super::input::InputScript;
+use super::limits::{LimitTracker, Limits, RunReport, StopReason};
+use super::png::PngOptions;
+
+/// Settings for running a ROM without printing anything to the terminal
+pub struct HeadlessOptions {
+    /// The number of frames to run
+    pub frames: usize,
+
+    /// Key presses and releases to replay
+    pub input: InputScript,
+
+    /// Hard limits on the run, on top of `frames`
+    pub limits: Limits,
+
+    /// Stop before `frames` once the program has halted or is stuck with no input coming
+    pub stop_when_halted: bool,
+
+    /// If set, every k-th frame is also written out while running
+    pub every: Option<usize>,
+
+    /// Where to write the frames, stdout is used when this is `None`
+    pub output: Option<PathBuf>,
+
+    /// The format the frames are written in
+    pub format: ImageFormat,
+
+    /// Frames at which a PNG screenshot is saved
+    pub screenshot_frames: Vec<usize>,
+
+    /// Where screenshots are saved, the frame number is added to the file name
+    pub screenshot_path: PathBuf,
+
+    /// The scale and palette of screenshots
+    pub screenshot_options: PngOptions,
+
+    /// Records a range of frames as an animated GIF
+    pub gif: Option<GifCapture>,
+}
+
+/// A range of frames to record as an animated GIF
+pub struct GifCapture {
+    /// Where the GIF is saved
+    pub path: PathBuf,
+
+    /// The first and last frame to record
+    pub frames: RangeInclusive<usize>,
+
+    /// The scale and palette of the GIF
+    pub options: GifOptions,
+}
+
+/// Runs a machine for a fixed number of frames and writes the final screen. The report
+/// says why the run stopped, which is [`StopReason::FrameLimit`] after running all
+/// `frames`.
+pub fn run_headless<E: Emulator>(
+    emulator: &mut E,
+    options: &HeadlessOptions,
+) -> io::Result<RunReport> {
+    let every = options.every.filter(|every| *every > 0);
+    let mut recorder = options
+        .gif
+        .as_ref()
+        .map(|gif| GifRecorder::new(WIDTH as usize, HEIGHT as usize, gif.options));
+
+    let limits = Limits {
+        max_frames: Some(
+            options
+                .limits
+                .max_frames
+                .map_or(options.frames, |max| max.min(options.frames)),
+        ),
+        ..options.limits
+    };
+    emulator.output().set_limit(limits.max_output_bytes);
+    let mut tracker = LimitTracker::new(limits);
+
+    let reason = loop {
+        let frame = tracker.frames + 1;
+        options.input.apply(frame, emulator.keys());
+        if let Some(reason) = emulator.run_frame_limited(&mut tracker) {
+            break reason;
+        }
+
+        let written = write_frame_outputs(emulator, frame, options, every, recorder.as_mut());
+        if emulator.output().exhausted() {
+            break StopReason::OutputLimit;
+        }
+        written?;
+
+        if options.stop_when_halted && emulator.is_finished(frame, &options.input) {
+            break StopReason::Finished(emulator.status());
+        }
+    };
+
+    // Whatever stopped the run, the files that were started are finished off so
+    // they stay readable, unless the output limit is what stopped it
+    let finished = match reason {
+        StopReason::OutputLimit => emulator.finish_sinks().or(Ok(())),
+        _ => finish_outputs(emulator, options, recorder.as_ref()),
+    };
+    if emulator.output().exhausted() {
+        return Ok(tracker.report(StopReason::OutputLimit, emulator.output()));
+    }
+    finished?;
+
+    Ok(tracker.report(reason, emulator.output()))
+}
+
+/// Captures GIF frames and writes the dumps and screenshots due at this frame
+fn write_frame_outputs<E: Emulator>(
+    emulator: &E,
+    frame: usize,
+    options: &HeadlessOptions,
+    every: Option<usize>,
+    recorder: Option<&mut GifRecorder>,
+) -> io::Result<()> {
+    let screen = emulator.screen();
+
+    if let (Some(recorder), Some(gif)) = (recorder, &options.gif) {
+        if gif.frames.contains(&frame) {
+            recorder.capture(&screen.pixels());
+        }
+    }
+
+    if every.is_some_and(|every| frame.is_multiple_of(every)) {
+        let path = options
+            .output
+            .as_deref()
+            .map(|output| numbered_path(output, frame));
+        screen.save(path.as_deref(), options.format, emulator.output())?;
+    }
+
+    if options.screenshot_frames.contains(&frame) {
+        let path = numbered_path(&options.screenshot_path, frame);
+        let format = ImageFormat::Png(options.screenshot_options);
+        screen.save(Some(&path), format, emulator.output())?;
+    }
+
+    Ok(())
+}
+
+/// Saves the GIF, finishes the sinks and writes the final frame
+fn finish_outputs<E: Emulator>(
+    emulator: &mut E,
+    options: &HeadlessOptions,
+    recorder: Option<&GifRecorder>,
+) -> io::Result<()> {
+    if let (Some(recorder), Some(gif)) = (recorder, &options.gif) {
+        let mut out = BufWriter::new(emulator.output().wrap(File::create(&gif.path)?));
+        recorder.write(&mut out)?;
+        out.flush()?;
+    }
+    emulator.finish_sinks()?;
+
+    emulator
+        .screen()
+        .save(options.output.as_deref(), options.format, emulator.output())
+}
+
+/// Inserts a zero padded frame number before the extension, `out.pbm` becomes `out_00060.pbm`
+pub fn numbered_path(path: &Path, frame: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}_{frame:05}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{frame:05}"),
+    };
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbered_path() {
+        assert_eq!(
+            numbered_path(Path::new("frames/out.pbm"), 60),
+            PathBuf::from("frames/out_00060.pbm")
+        );
+        assert_eq!(
+            numbered_path(Path::new("out"), 1),
+            PathBuf::from("out_00001")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+/// A key press or release scheduled for the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame the event happens on, counting from 1
+    pub frame: usize,
+
+    /// The key on the hex keypad, 0x0 to 0xF
+    pub key: u8,
+
+    /// Whether the key goes down or up
+    pub pressed: bool,
+}
+
+/// Key presses and releases to replay during a run.
+///
+/// Entries are `<frame> press|release <key>` with the key as a hex digit, separated by
+/// newlines or `;`, e.g. `10 press 5; 20 release 5`. Lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputScript {
+    pub events: Vec<KeyEvent>,
+}
+
+impl InputScript {
+    /// Applies the events scheduled for `frame` to the keypad
+    pub fn apply(&self, frame: usize, keys: &mut [bool; 16]) {
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            keys[event.key as usize] = event.pressed;
+        }
+    }
+
+    /// Whether anything is scheduled after `frame`
+    pub fn has_events_after(&self, frame: usize) -> bool {
+        self.events.iter().any(|event| event.frame > frame)
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+
+        for entry in script.split([';', '\n']) {
+            let entry = entry.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("invalid input entry `{entry}`");
+            let parts: Vec<&str> = entry.split_whitespace().collect();
+            let [frame, action, key] = parts[..] else {
+                return Err(invalid());
+            };
+
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let pressed = match action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(invalid()),
+            };
+            let key = u8::from_str_radix(key.trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(invalid)?;
+
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+
+        Ok(InputScript { events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input_script() {
+        let script: InputScript = "10 press 5; 20 release 0xA\n# comment\n".parse().unwrap();
+
+        assert_eq!(
+            script.events,
+            vec![
+                KeyEvent {
+                    frame: 10,
+                    key: 0x5,
+                    pressed: true
+                },
+                KeyEvent {
+                    frame: 20,
+                    key: 0xA,
+                    pressed: false
+                },
+            ]
+        );
+
+        assert!("10 push 5".parse::<InputScript>().is_err());
+        assert!("10 press 16".parse::<InputScript>().is_err());
+    }
+
+    #[test]
+    fn test_apply_input_script() {
+        let script: InputScript = "2 press F; 3 release F".parse().unwrap();
+        let mut keys = [false; 16];
+
+        script.apply(1, &mut keys);
+        assert!(!keys[0xF]);
+
+        script.apply(2, &mut keys);
+        assert!(keys[0xF]);
+
+        script.apply(3, &mut keys);
+        assert!(!keys[0xF]);
+    }
+}
@@ -1,8 +1,8 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
 use rand::Rng;
 
 use super::cpu::CPU;
+use super::font::{BIG_GLYPH_HEIGHT, SMALL_FONT_SIZE, SMALL_GLYPH_HEIGHT};
+use super::halt::RunStatus;
 
 impl CPU {
     /// Clear the display.
@@ -12,6 +12,11 @@ impl CPU {
 
     /// Jump to location nnn.   
     pub fn jp1nnn(&mut self, nnn: u16) {
+        // A jump to itself can never be left again
+        if nnn == self.pc.wrapping_sub(2) {
+            self.status = RunStatus::Halted { pc: nnn };
+        }
+
         self.pc = nnn;
     }
 
@@ -22,7 +27,7 @@ impl CPU {
 
     /// Set Vx = Vx + nn.
     pub fn add7xnn(&mut self, x: u8, nn: u8) {
-        self.registers[x as usize] += nn;
+        self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn);
     }
 
     /// Set I = nnn.
@@ -37,43 +42,46 @@ impl CPU {
 
     /// Return from a subroutine.
     pub fn ret00ee(&mut self) {
-        self.sp -= 1;
-        self.pc = self.stack[self.sp as usize];
+        match self.pop_return() {
+            Ok(address) => self.pc = address,
+            Err(fault) => self.fault(fault),
+        }
     }
 
     /// Call subroutine at nnn.
     pub fn call2nnn(&mut self, nnn: u16) {
-        self.sp += 1;
-        self.stack[self.sp as usize] = nnn;
-
-        self.pc = nnn;
+        // pc already points past the call, which is where the subroutine returns to
+        match self.push_return(self.pc) {
+            Ok(()) => self.pc = nnn,
+            Err(fault) => self.fault(fault),
+        }
     }
 
     /// Skip next instruction if Vx = nn.
     pub fn se3xnn(&mut self, x: u8, nn: u8) {
         if self.registers[x as usize] == nn {
-            self.pc += 2;
+            self.advance_pc();
         }
     }
 
     /// Skip next instruction if Vx != nn.
     pub fn sne4xnn(&mut self, x: u8, nn: u8) {
         if self.registers[x as usize] != nn {
-            self.pc += 2;
+            self.advance_pc();
         }
     }
 
     /// Skip next instruction if Vx = Vy.
     pub fn se5xy0(&mut self, x: u8, y: u8) {
         if self.registers[x as usize] == self.registers[y as usize] {
-            self.pc += 2;
+            self.advance_pc();
         }
     }
 
     /// Skip next instruction if Vx != Vy.
     pub fn sne9xy0(&mut self, x: u8, y: u8) {
         if self.registers[x as usize] != self.registers[y as usize] {
-            self.pc += 2;
+            self.advance_pc();
         }
     }
 
@@ -99,41 +107,26 @@ impl CPU {
 
     /// Set Vx = Vx + Vy, set VF = carry.
     pub fn add8xy4(&mut self, x: u8, y: u8) {
-        // Set VF to 0
-        self.vf = 0;
-
-        // The sum casted into a u16
-        let sum = self.registers[x as usize] as u16 + self.registers[y as usize] as u16;
-
-        // If the sum overflows set VF to 1 and only write the first byte to Vx
-        if sum > 255 {
-            self.vf = 1;
-            self.registers[x as usize] = (sum & 0xFF) as u8;
-        } else {
-            self.registers[x as usize] = sum as u8;
-        }
+        let (sum, carry) = self.registers[x as usize].overflowing_add(self.registers[y as usize]);
+
+        self.registers[x as usize] = sum;
+        self.set_flag(carry as u8);
     }
 
     /// Set Vx = Vx - Vy, set VF = NOT borrow.
     pub fn sub8xy5(&mut self, x: u8, y: u8) {
         let difference = self.registers[x as usize] as i16 - self.registers[y as usize] as i16;
-        self.vf = 0;
 
-        if difference > 0 {
-            self.vf = 1;
-        }
         self.registers[x as usize] = difference as u8;
+        self.set_flag((difference > 0) as u8);
     }
 
     /// Set Vx = Vy - Vx, set VF = NOT borrow.
     pub fn sub8xy7(&mut self, x: u8, y: u8) {
         let difference = self.registers[y as usize] as i16 - self.registers[x as usize] as i16;
-        self.vf = 0;
 
-        if difference > 0 {
-            self.vf = 1;
-        }
         self.registers[x as usize] = difference as u8;
+        self.set_flag((difference > 0) as u8);
     }
 
     /// Set Vx = Vy SHR 1, set VF = shifted bit
@@ -144,8 +137,8 @@ impl CPU {
         // The shifted bit
         let y_shifted = self.registers[y as usize] >> 1;
 
-        self.vf = last_bit;
         self.registers[x as usize] = y_shifted;
+        self.set_flag(last_bit);
     }
 
     /// Set Vx = Vx SHR 1, set VF = shifted bit
@@ -156,44 +149,43 @@ impl CPU {
         // The shifted bit
         let x_shifted = self.registers[x as usize] >> 1;
 
-        self.vf = last_bit;
         self.registers[x as usize] = x_shifted;
+        self.set_flag(last_bit);
     }
 
     /// Set Vx = Vy SHL 1, set VF = shifted bit
     pub fn shl8xye_usey(&mut self, x: u8, y: u8) {
-        // Get the last bit by checking if the byte is greater than 127
-        let last_bit = if self.registers[y as usize] > 127 {
-            1
-        } else {
-            0
-        };
+        // Get the first bit
+        let first_bit = self.registers[y as usize] >> 7;
 
         // The shifted bit
         let y_shifted = self.registers[y as usize] << 1;
 
-        self.vf = last_bit;
         self.registers[x as usize] = y_shifted;
+        self.set_flag(first_bit);
     }
 
     /// Set Vx = Vx SHL 1, set VF = shifted bit
     pub fn shl8xye_usex(&mut self, x: u8, _y: u8) {
-        // Get the last bit by checking if the byte is greater than 127
-        let last_bit = if self.registers[x as usize] > 127 {
-            1
-        } else {
-            0
-        };
+        // Get the first bit
+        let first_bit = self.registers[x as usize] >> 7;
+
         // The shifted bit
         let x_shifted = self.registers[x as usize] << 1;
 
-        self.vf = last_bit;
         self.registers[x as usize] = x_shifted;
+        self.set_flag(first_bit);
+    }
+
+    /// Set VF, the flag register. Instructions that compute a flag write it after their
+    /// result, so the flag wins when Vx is VF itself.
+    pub fn set_flag(&mut self, flag: u8) {
+        self.registers[0xF] = flag;
     }
 
     /// Jump to location nnn + V0.
     pub fn jpbnnn(&mut self, nnn: u16) {
-        self.pc = nnn + (self.registers[0] as u16);
+        self.pc = self.wrap_address(nnn as usize + self.registers[0] as usize);
     }
 
     /// Set Vx = random byte AND kk.
@@ -204,73 +196,70 @@ impl CPU {
     }
 
     /// Skip next instruction if key with the value of Vx is pressed.
-    #[allow(dead_code)]
-    pub fn skpex9e(&mut self) {
-        todo!()
+    pub fn skpex9e(&mut self, x: u8) {
+        if self.keys[(self.registers[x as usize] & 0xF) as usize] {
+            self.advance_pc();
+        }
     }
 
     /// Skip next instruction if key with the value of Vx is not pressed.
-    #[allow(dead_code)]
-    pub fn skpexa1(&mut self) {
-        todo!()
+    pub fn skpexa1(&mut self, x: u8) {
+        if !self.keys[(self.registers[x as usize] & 0xF) as usize] {
+            self.advance_pc();
+        }
     }
 
     /// Set Vx = delay timer value.
     pub fn ldfx07(&mut self, x: u8) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-
-        let then = self.last_dt_write;
-
-        let elapsed_time_wrapped = ((now - then) % 255) as u8;
-
-        self.registers[x as usize] = elapsed_time_wrapped;
+        self.registers[x as usize] = self.delay_timer;
+    }
+
+    /// Wait for a key press, store the value of the key in Vx.
+    pub fn ldfx0a(&mut self, x: u8) {
+        match self.keys.iter().position(|pressed| *pressed) {
+            Some(key) => self.registers[x as usize] = key as u8,
+            // Run this instruction again until a key is pressed
+            None => {
+                self.rewind_pc();
+                self.status = RunStatus::WaitingForKey { pc: self.pc };
+                if let Some(observer) = &mut self.observer {
+                    observer.on_key_wait(x);
+                }
+            }
+        }
     }
 
     /// Set delay timer = Vx.
     pub fn ldfx15(&mut self, x: u8) {
-        self.last_dt_write = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        self.delay_timer = x;
+        self.delay_timer = self.registers[x as usize];
     }
 
     /// Set sound timer = Vx.
     pub fn ldfx18(&mut self, x: u8) {
-        self.last_st_write = std::time::SystemTime::now().elapsed().unwrap().as_millis();
-        self.sound_timer = x;
+        self.set_sound_timer(self.registers[x as usize]);
     }
 
     /// Set I = I + Vx.
     pub fn addfx1e(&mut self, x: u8) {
-        self.i_reg += self.registers[x as usize] as u16;
+        let sum = self.i_reg as usize + self.registers[x as usize] as usize;
+        self.check_index(sum);
+        // I is 16 bits wide, a carry wraps it around
+        self.i_reg = sum as u16;
     }
 
-    /// Set I = location of sprite for digit Vx.
+    /// Set I = location of sprite for digit Vx. Only the low nibble counts, like on the
+    /// VIP.
     pub fn ldfx29(&mut self, x: u8) {
-        self.i_reg = 0x50
-            + match self.registers[x as usize] {
-                0x0 => 0,
-                0x1 => 5,
-                0x2 => 10,
-                0x3 => 15,
-                0x4 => 20,
-                0x5 => 25,
-                0x6 => 30,
-                0x7 => 35,
-                0x8 => 40,
-                0x9 => 45,
-                0xA => 50,
-                0xB => 55,
-                0xC => 60,
-                0xD => 65,
-                0xE => 70,
-                0xF => 75,
-                _ => 0,
-            }
+        let digit = self.registers[x as usize] & 0xF;
+        self.i_reg = self.machine.font_address + digit as u16 * SMALL_GLYPH_HEIGHT as u16;
+    }
+
+    /// Set I = location of big sprite for digit Vx.
+    pub fn ldfx30(&mut self, x: u8) {
+        let digit = self.registers[x as usize] & 0xF;
+        self.i_reg = self.machine.font_address
+            + SMALL_FONT_SIZE as u16
+            + digit as u16 * BIG_GLYPH_HEIGHT as u16;
     }
 
     /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
@@ -285,24 +274,22 @@ impl CPU {
             digits[i] = char.to_digit(10).unwrap() as u8;
         }
 
-        println!("{digits:?}");
-
-        self.mem[self.i_reg as usize] = digits[0];
-        self.mem[self.i_reg as usize + 1] = digits[1];
-        self.mem[self.i_reg as usize + 2] = digits[2];
+        for (offset, digit) in digits.into_iter().enumerate() {
+            self.write_data(self.i_reg as usize + offset, digit);
+        }
     }
 
     /// Store registers V0 through Vx in memory starting at location I.
     pub fn ldfx55(&mut self, x: u8) {
         for i in 0..(x + 1) {
-            self.mem[(self.i_reg + i as u16) as usize] = self.registers[i as usize];
+            self.write_data(self.i_reg as usize + i as usize, self.registers[i as usize]);
         }
     }
 
     /// Read registers V0 through Vx from memory starting at location I.
     pub fn ldfx65(&mut self, x: u8) {
         for i in 0..(x + 1) {
-            self.registers[i as usize] = self.mem[(self.i_reg + i as u16) as usize];
+            self.registers[i as usize] = self.read_data(self.i_reg as usize + i as usize);
         }
     }
 }
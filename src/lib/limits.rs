@@ -0,0 +1,427 @@
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::cpu::CPU;
+use super::halt::RunStatus;
+use super::input::InputScript;
+
+/// How long a run may take when no other limit is given, so nothing runs forever by default
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(10);
+
+/// Hard limits on how much work a run may do, for running ROMs that can't be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most instructions to execute
+    pub max_instructions: Option<u64>,
+
+    /// The most 60 Hz frames to run
+    pub max_frames: Option<usize>,
+
+    /// The most wall-clock time to spend, checked before every instruction
+    pub max_duration: Option<Duration>,
+
+    /// The most bytes all outputs wrapped by [`OutputBudget::wrap`] may write together
+    pub max_output_bytes: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_instructions: None,
+            max_frames: None,
+            max_duration: Some(DEFAULT_MAX_DURATION),
+            max_output_bytes: None,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all, the run only ends when the program halts
+    pub fn unlimited() -> Self {
+        Limits {
+            max_duration: None,
+            ..Limits::default()
+        }
+    }
+}
+
+/// Why a limited run stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program halted or got stuck with no more input coming, see [`RunStatus`]
+    Finished(RunStatus),
+
+    /// [`Limits::max_instructions`] was reached
+    InstructionLimit,
+
+    /// [`Limits::max_frames`] was reached
+    FrameLimit,
+
+    /// [`Limits::max_duration`] was reached
+    TimeLimit,
+
+    /// [`Limits::max_output_bytes`] was reached
+    OutputLimit,
+
+    /// The instruction at `pc` faulted, see [`RunStatus::Faulted`]
+    Fault { pc: u16, message: String },
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Finished(status) => write!(f, "{status}"),
+            StopReason::InstructionLimit => write!(f, "instruction limit reached"),
+            StopReason::FrameLimit => write!(f, "frame limit reached"),
+            StopReason::TimeLimit => write!(f, "time limit reached"),
+            StopReason::OutputLimit => write!(f, "output limit reached"),
+            StopReason::Fault { pc, message } => write!(f, "fault at {pc:#05x}: {message}"),
+        }
+    }
+}
+
+/// What a limited run did before it stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunReport {
+    pub reason: StopReason,
+
+    /// Instructions executed, including the one that faulted
+    pub instructions: u64,
+
+    /// Frames run to the end
+    pub frames: usize,
+
+    /// Bytes written to outputs wrapped by the CPU's [`OutputBudget`]
+    pub output_bytes: u64,
+
+    pub elapsed: Duration,
+}
+
+/// Counts the bytes written by every writer it wraps and refuses writes past a shared limit.
+/// Clones share the same count.
+#[derive(Debug, Clone)]
+pub struct OutputBudget {
+    used: Arc<AtomicU64>,
+
+    /// `u64::MAX` when there is no limit
+    limit: Arc<AtomicU64>,
+}
+
+impl Default for OutputBudget {
+    fn default() -> Self {
+        OutputBudget::new(None)
+    }
+}
+
+impl OutputBudget {
+    pub fn new(limit: Option<u64>) -> Self {
+        OutputBudget {
+            used: Arc::new(AtomicU64::new(0)),
+            limit: Arc::new(AtomicU64::new(limit.unwrap_or(u64::MAX))),
+        }
+    }
+
+    /// Changes the limit, which also applies to writers that are already wrapped
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit
+            .store(limit.unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// The number of bytes written so far
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Whether a write was refused because it would have gone over the limit
+    pub fn exhausted(&self) -> bool {
+        self.used() > self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Wraps a writer so its output counts against this budget
+    pub fn wrap<W: Write>(&self, inner: W) -> LimitedWriter<W> {
+        LimitedWriter {
+            inner,
+            budget: self.clone(),
+        }
+    }
+}
+
+/// A writer whose output counts against an [`OutputBudget`]
+pub struct LimitedWriter<W> {
+    inner: W,
+    budget: OutputBudget,
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let limit = self.budget.limit.load(Ordering::Relaxed);
+        let used = self.budget.used();
+
+        if used.saturating_add(buf.len() as u64) > limit {
+            // Push the count past the limit so the budget reports itself exhausted
+            self.budget
+                .used
+                .store(limit.saturating_add(1), Ordering::Relaxed);
+            return Err(io::Error::other(format!(
+                "output limit of {limit} bytes reached"
+            )));
+        }
+
+        let written = self.inner.write(buf)?;
+        self.budget
+            .used
+            .fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for LimitedWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Keeps track of a limited run across frames
+pub struct LimitTracker {
+    limits: Limits,
+    started: Instant,
+    pub instructions: u64,
+    pub frames: usize,
+}
+
+impl LimitTracker {
+    /// Starts the clock for a run
+    pub fn new(limits: Limits) -> Self {
+        LimitTracker {
+            limits,
+            started: Instant::now(),
+            instructions: 0,
+            frames: 0,
+        }
+    }
+
+    /// The limit that keeps the next frame from starting, if there is one
+    pub fn check_before_frame(&self, output: &OutputBudget) -> Option<StopReason> {
+        if self.limits.max_frames.is_some_and(|max| self.frames >= max) {
+            return Some(StopReason::FrameLimit);
+        }
+        if self.out_of_time() {
+            return Some(StopReason::TimeLimit);
+        }
+        if output.exhausted() {
+            return Some(StopReason::OutputLimit);
+        }
+
+        None
+    }
+
+    /// Whether [`Limits::max_duration`] has passed
+    pub fn out_of_time(&self) -> bool {
+        self.limits
+            .max_duration
+            .is_some_and(|max| self.started.elapsed() >= max)
+    }
+
+    /// Sums up the run so far
+    pub fn report(&self, reason: StopReason, output: &OutputBudget) -> RunReport {
+        RunReport {
+            reason,
+            instructions: self.instructions,
+            frames: self.frames,
+            output_bytes: output.used(),
+            elapsed: self.started.elapsed(),
+        }
+    }
+}
+
+impl CPU {
+    /// Runs one frame like [`CPU::run_frame`], unless a limit has been reached. A fault
+    /// stops the frame with [`StopReason::Fault`].
+    pub fn run_frame_limited(&mut self, tracker: &mut LimitTracker) -> Option<StopReason> {
+        let limits = tracker.limits;
+
+        if let Some(reason) = tracker.check_before_frame(&self.output) {
+            return Some(reason);
+        }
+
+        // A stalled CPU executes nothing until the frame ends
+        let stop = self.run_frame_instructions(|cpu| {
+            if limits
+                .max_instructions
+                .is_some_and(|max| tracker.instructions >= max)
+            {
+                return Some(StopReason::InstructionLimit);
+            }
+            // A single frame can run for as long as it likes under some timings
+            if tracker.out_of_time() {
+                return Some(StopReason::TimeLimit);
+            }
+
+            tracker.instructions += 1;
+            if let RunStatus::Faulted { pc, fault } = cpu.step() {
+                return Some(StopReason::Fault {
+                    pc,
+                    message: fault.to_string(),
+                });
+            }
+
+            None
+        });
+        if stop.is_some() {
+            return stop;
+        }
+
+        self.end_frame();
+        tracker.frames += 1;
+
+        None
+    }
+
+    /// Runs with scripted input until the program halts or a limit is reached. Outputs
+    /// only count against [`Limits::max_output_bytes`] if they were wrapped by the CPU's
+    /// [`OutputBudget`].
+    pub fn run_limited(&mut self, limits: Limits, input: &InputScript) -> RunReport {
+        self.output.set_limit(limits.max_output_bytes);
+        let mut tracker = LimitTracker::new(limits);
+
+        loop {
+            let frame = tracker.frames + 1;
+            input.apply(frame, &mut self.keys);
+
+            if let Some(reason) = self.run_frame_limited(&mut tracker) {
+                return tracker.report(reason, &self.output);
+            }
+            if self.output.exhausted() {
+                return tracker.report(StopReason::OutputLimit, &self.output);
+            }
+            if self.is_finished(frame, input) {
+                return tracker.report(StopReason::Finished(self.status), &self.output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::framebuffer::Framebuffer;
+    use crate::sink::FrameSink;
+    use crate::timing::Timing;
+
+    /// Writes the whole display buffer every frame
+    struct RawSink<W>(W);
+
+    impl<W: Write> FrameSink for RawSink<W> {
+        fn present(&mut self, frame: &Framebuffer, _sound: bool) -> io::Result<()> {
+            self.0.write_all(&frame.pixels())
+        }
+    }
+
+    /// 0x200: 7001 (V0 += 1), 0x202: 1200 (loop), which never halts or repeats a state
+    /// before V0 wraps
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut cpu = CPU::new_with_memory(&COUNTER);
+        let limits = Limits {
+            max_instructions: Some(25),
+            ..Limits::default()
+        };
+
+        let report = cpu.run_limited(limits, &InputScript::default());
+
+        assert_eq!(report.reason, StopReason::InstructionLimit);
+        assert_eq!(report.instructions, 25);
+        assert_eq!(report.frames, 2);
+    }
+
+    #[test]
+    fn test_frame_and_time_limits() {
+        let limits = Limits {
+            max_frames: Some(3),
+            ..Limits::default()
+        };
+        let report = CPU::new_with_memory(&COUNTER).run_limited(limits, &InputScript::default());
+        assert_eq!(report.reason, StopReason::FrameLimit);
+        assert_eq!(report.frames, 3);
+
+        let limits = Limits {
+            max_duration: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        let report = CPU::new_with_memory(&COUNTER).run_limited(limits, &InputScript::default());
+        assert_eq!(report.reason, StopReason::TimeLimit);
+        assert_eq!(report.frames, 0);
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let mut cpu = CPU::new_with_memory(&COUNTER);
+        let sink = RawSink(cpu.output.wrap(Vec::new()));
+        cpu.sinks.push(Box::new(sink));
+        let limits = Limits {
+            max_output_bytes: Some(5000),
+            ..Limits::default()
+        };
+
+        let report = cpu.run_limited(limits, &InputScript::default());
+
+        // Two 2048 byte frames fit, the third doesn't
+        assert_eq!(report.reason, StopReason::OutputLimit);
+        assert_eq!(report.frames, 3);
+        assert!(cpu.finish_sinks().is_err());
+    }
+
+    #[test]
+    fn test_faults_stop_the_run() {
+        // 0x200: 00EE, returning with nothing on the stack
+        let mut cpu = CPU::new_with_memory(&[0x00, 0xEE]);
+
+        let report = cpu.run_limited(Limits::default(), &InputScript::default());
+
+        assert_eq!(
+            report.reason,
+            StopReason::Fault {
+                pc: 0x200,
+                message: "stack underflow, returned with no call to return from".to_string()
+            }
+        );
+        assert_eq!(report.instructions, 1);
+    }
+
+    #[test]
+    fn test_time_limit_cuts_a_frame_short() {
+        let mut cpu = CPU::new_with_memory(&COUNTER);
+        cpu.timing = Timing::Instructions(usize::MAX);
+        let limits = Limits {
+            max_duration: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        };
+
+        let report = cpu.run_limited(limits, &InputScript::default());
+
+        assert_eq!(report.reason, StopReason::TimeLimit);
+        assert_eq!(report.frames, 0);
+        assert!(report.instructions > 0);
+    }
+
+    #[test]
+    fn test_halt_ends_the_run() {
+        let mut cpu = CPU::new_with_memory(&[0x12, 0x00]);
+
+        let report = cpu.run_limited(Limits::unlimited(), &InputScript::default());
+
+        assert_eq!(
+            report.reason,
+            StopReason::Finished(RunStatus::Halted { pc: 0x200 })
+        );
+    }
+}
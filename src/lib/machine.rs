@@ -0,0 +1,270 @@
+use std::str::FromStr;
+
+use super::bus::Ram;
+use super::cpu::CPU;
+use super::font::{FontSet, BIG_FONT_SIZE, SMALL_FONT_SIZE};
+use super::png::crc32;
+use super::stack::{vip_stack_base, VIP_WORK_AREA};
+use super::sys::SysRoutines;
+
+/// The memory sizes CHIP-8 machines came with: a 2 KiB VIP, 4 KiB and XO-CHIP's 64 KiB
+pub const MEMORY_SIZES: [usize; 3] = [2048, 4096, 65536];
+
+/// The memory layout of a CHIP-8 machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineConfig {
+    /// Bytes of memory, one of [`MEMORY_SIZES`]
+    pub memory_size: usize,
+
+    /// Where the ROM is loaded and execution starts
+    pub load_address: u16,
+
+    /// Where the hex digit font is stored, what Fx29 and Fx30 point I into
+    pub font_address: u16,
+
+    /// The glyphs stored there, the small font followed by the big one
+    pub font: FontSet,
+
+    /// The interpreter routines `0nnn` can call
+    pub sys_routines: SysRoutines,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig::chip8()
+    }
+}
+
+impl MachineConfig {
+    /// 4 KiB with programs at 0x200, what most ROMs expect
+    pub fn chip8() -> Self {
+        MachineConfig {
+            memory_size: 4096,
+            load_address: 0x200,
+            font_address: 0x050,
+            font: FontSet::chip8(),
+            sys_routines: SysRoutines::Vip,
+        }
+    }
+
+    /// An unexpanded 2 KiB COSMAC VIP, with its font
+    pub fn vip_2k() -> Self {
+        MachineConfig {
+            memory_size: 2048,
+            font: FontSet::vip(),
+            ..MachineConfig::chip8()
+        }
+    }
+
+    /// The ETI-660, which loads programs at 0x600 and has its own font
+    pub fn eti660() -> Self {
+        MachineConfig {
+            load_address: 0x600,
+            font: FontSet::eti660(),
+            ..MachineConfig::chip8()
+        }
+    }
+
+    /// A VIP running the two page hi-res interpreter, whose ROMs call its clear screen
+    /// routine with `0230`
+    pub fn hires() -> Self {
+        MachineConfig {
+            sys_routines: SysRoutines::TwoPageHires,
+            ..MachineConfig::chip8()
+        }
+    }
+
+    /// XO-CHIP's 64 KiB
+    pub fn xochip() -> Self {
+        MachineConfig {
+            memory_size: 65536,
+            ..MachineConfig::chip8()
+        }
+    }
+
+    /// Checks that the layout makes sense and `rom` fits in memory after the load address
+    /// without overwriting the font
+    pub fn check(&self, rom: &[u8]) -> Result<(), String> {
+        if !MEMORY_SIZES.contains(&self.memory_size) {
+            return Err(format!(
+                "memory size must be 2048, 4096 or 65536 bytes, got {}",
+                self.memory_size
+            ));
+        }
+        if self.font_address as usize + SMALL_FONT_SIZE + BIG_FONT_SIZE > self.memory_size {
+            return Err(format!(
+                "a font at {:#05x} doesn't fit in {} bytes of memory",
+                self.font_address, self.memory_size
+            ));
+        }
+
+        // The VIP keeps its stack and variables at the top of RAM, above the program
+        let work_area = vip_stack_base(self.memory_size);
+        if work_area < self.load_address as usize {
+            return Err(format!(
+                "the VIP stack and the {VIP_WORK_AREA:#x} bytes of interpreter work area at the top of {} bytes of memory start at {work_area:#05x}, below the load address {:#05x}",
+                self.memory_size, self.load_address
+            ));
+        }
+
+        let space = self.memory_size.saturating_sub(self.load_address as usize);
+        if rom.len() > space {
+            return Err(format!(
+                "the ROM is {} bytes, but only {space} fit between {:#05x} and the end of {} bytes of memory",
+                rom.len(),
+                self.load_address,
+                self.memory_size
+            ));
+        }
+
+        let font = self.font_address as usize
+            ..self.font_address as usize + SMALL_FONT_SIZE + BIG_FONT_SIZE;
+        let program = self.load_address as usize..self.load_address as usize + rom.len();
+        if !program.is_empty() && font.start < program.end && program.start < font.end {
+            return Err(format!(
+                "the font at {:#05x}..{:#05x} overlaps the ROM at {:#05x}..{:#05x}",
+                font.start, font.end, program.start, program.end
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for MachineConfig {
+    type Err = String;
+
+    /// Parses a comma separated list of presets (`chip8`, `vip2k`, `eti660`, `hires`, `xochip`)
+    /// and `memory=`, `load=` and `font=` settings, later entries overriding earlier ones,
+    /// e.g. `eti660,memory=2048`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut machine = MachineConfig::default();
+
+        for entry in value.split(',').map(str::trim) {
+            let invalid = || format!("invalid machine setting `{entry}`");
+
+            match entry.split_once('=') {
+                None => {
+                    machine = match entry {
+                        "chip8" => MachineConfig::chip8(),
+                        "vip2k" => MachineConfig::vip_2k(),
+                        "eti660" => MachineConfig::eti660(),
+                        "hires" => MachineConfig::hires(),
+                        "xochip" => MachineConfig::xochip(),
+                        _ => return Err(format!("unknown machine `{entry}`")),
+                    }
+                }
+                Some((key, number)) => {
+                    let number = number.trim();
+                    let number = match number.strip_prefix("0x") {
+                        Some(hex) => usize::from_str_radix(hex, 16),
+                        None => number.parse(),
+                    }
+                    .map_err(|_| invalid())?;
+                    let address = || u16::try_from(number).map_err(|_| invalid());
+
+                    match key.trim() {
+                        "memory" => machine.memory_size = number,
+                        "load" => machine.load_address = address()?,
+                        "font" => machine.font_address = address()?,
+                        _ => return Err(invalid()),
+                    }
+                }
+            }
+        }
+
+        machine.check(&[])?;
+        Ok(machine)
+    }
+}
+
+impl CPU {
+    /// A CPU for the given machine with the font and `program` loaded, or an error if
+    /// the layout is invalid or the program doesn't fit
+    pub fn with_machine(machine: MachineConfig, program: &[u8]) -> Result<Self, String> {
+        machine.check(program)?;
+
+        let mut cpu = CPU::new();
+        cpu.bus = Box::new(Ram::new(machine.memory_size));
+        machine.font.load(cpu.bus.as_mut(), machine.font_address);
+        cpu.bus.load(machine.load_address, program);
+        cpu.pc = machine.load_address;
+        cpu.sys_handlers.extend(machine.sys_routines.handlers());
+        cpu.machine = machine;
+        cpu.rom_hash = Some(crc32(program));
+        cpu.rom_size = program.len();
+
+        Ok(cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_machine() {
+        assert_eq!("eti660".parse(), Ok(MachineConfig::eti660()));
+        assert_eq!(
+            "vip2k, font=0x000, load=0x300".parse(),
+            Ok(MachineConfig {
+                memory_size: 2048,
+                load_address: 0x300,
+                font_address: 0x000,
+                font: FontSet::vip(),
+                sys_routines: SysRoutines::Vip,
+            })
+        );
+
+        assert!("memory=3000".parse::<MachineConfig>().is_err());
+        assert!("vip2k,font=0x7F0".parse::<MachineConfig>().is_err());
+        assert!("c64".parse::<MachineConfig>().is_err());
+        assert_eq!(
+            "vip2k,load=0x700".parse::<MachineConfig>(),
+            Err("the VIP stack and the 0x160 bytes of interpreter work area at the top of 2048 bytes of memory start at 0x6a0, below the load address 0x700".to_string())
+        );
+    }
+
+    #[test]
+    fn test_eti660_layout() {
+        let machine = "eti660,font=0x100".parse().unwrap();
+        // 0x600: F029 (I = the glyph for V0)
+        let mut cpu = CPU::with_machine(machine, &[0xF0, 0x29]).unwrap();
+        assert_eq!(cpu.pc, 0x600);
+        assert_eq!(cpu.bus.peek(0x600), 0xF0);
+        assert_eq!(cpu.bus.peek(0x100), FontSet::eti660().small[0]);
+
+        cpu.step();
+        assert_eq!(cpu.i_reg, 0x100);
+    }
+
+    #[test]
+    fn test_fonts_overlapping_the_rom_are_rejected() {
+        let machine: MachineConfig = "chip8,font=0x200".parse().unwrap();
+        assert_eq!(
+            CPU::with_machine(machine, &[0x00, 0xE0]).err(),
+            Some("the font at 0x200..0x2f0 overlaps the ROM at 0x200..0x202".to_string())
+        );
+
+        // Right below the ROM is fine
+        let machine: MachineConfig = "chip8,font=0x110".parse().unwrap();
+        assert!(CPU::with_machine(machine, &[0x00, 0xE0]).is_ok());
+        let machine: MachineConfig = "chip8,font=0x111".parse().unwrap();
+        assert!(CPU::with_machine(machine, &[0x00, 0xE0]).is_err());
+    }
+
+    #[test]
+    fn test_oversized_roms_are_rejected() {
+        let rom = vec![0; 0x600];
+
+        assert!(CPU::with_machine(MachineConfig::vip_2k(), &rom).is_ok());
+        let err = CPU::with_machine(MachineConfig::vip_2k(), &[rom.as_slice(), &[0]].concat())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            "the ROM is 1537 bytes, but only 1536 fit between 0x200 and the end of 2048 bytes of memory"
+        );
+        assert!(CPU::with_machine(MachineConfig::xochip(), &vec![0; 0xFE00]).is_ok());
+    }
+}
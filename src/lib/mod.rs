@@ -1,3 +1,33 @@
+pub mod bus;
+pub mod cdp1802;
 pub mod cpu;
+pub mod crash;
+pub mod disasm;
 pub mod display;
-pub mod instructions;
\ No newline at end of file
+pub mod emulator;
+pub mod export;
+pub mod extension;
+pub mod font;
+pub mod framebuffer;
+pub mod gif;
+pub mod halt;
+pub mod headless;
+pub mod input;
+pub mod instructions;
+pub mod limits;
+pub mod machine;
+pub mod observer;
+pub mod ocr;
+pub mod png;
+pub mod protect;
+pub mod quirks;
+pub mod sink;
+pub mod snapshot;
+pub mod stack;
+pub mod suite;
+pub mod sys;
+pub mod timing;
+pub mod unknown;
+pub mod vip;
+pub mod wav;
+pub mod y4m;
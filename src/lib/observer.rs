@@ -0,0 +1,134 @@
+use super::cpu::CPU;
+use super::framebuffer::Framebuffer;
+use super::protect::Diagnostic;
+
+/// Callbacks for watching a running program from outside the core, for profilers, loggers,
+/// achievements or front ends. Every method does nothing unless overridden. A CPU without
+/// an observer attached only pays for checking that there is none.
+pub trait Observer {
+    /// Before the instruction at `pc` executes
+    fn on_instruction(&mut self, _pc: u16, _opcode: u16) {}
+
+    /// After an instruction changed a byte of memory
+    fn on_memory_write(&mut self, _address: u16, _old: u8, _new: u8) {}
+
+    /// After Dxyn drew `rows` rows at (x, y), already wrapped onto the screen
+    fn on_draw(&mut self, _x: usize, _y: usize, _rows: usize, _collided: bool) {}
+
+    /// When the sound timer starts running
+    fn on_sound_start(&mut self) {}
+
+    /// When the sound timer runs out or is set to 0
+    fn on_sound_stop(&mut self) {}
+
+    /// At the end of every frame, with the screen as it was presented
+    fn on_frame(&mut self, _frame: &Framebuffer) {}
+
+    /// Every time Fx0A runs with no key down, storing the key into Vx once there is one
+    fn on_key_wait(&mut self, _x: u8) {}
+
+    /// When memory protection finds a suspicious access, see [`Protection`]
+    ///
+    /// [`Protection`]: super::protect::Protection
+    fn on_diagnostic(&mut self, _diagnostic: &Diagnostic) {}
+}
+
+impl CPU {
+    /// Writes a byte of memory on behalf of an instruction, telling the observer and
+    /// counting the change for idle detection
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        let old = self.bus.peek(address);
+        self.bus.write(address, value);
+        if old != value {
+            self.memory_changes += 1;
+        }
+
+        if let Some(observer) = &mut self.observer {
+            observer.on_memory_write(address, old, value);
+        }
+    }
+
+    /// Sets the sound timer, telling the observer if the tone starts or stops
+    pub fn set_sound_timer(&mut self, value: u8) {
+        let was_sounding = self.sound_timer > 0;
+        self.sound_timer = value;
+
+        if let Some(observer) = &mut self.observer {
+            match (was_sounding, value > 0) {
+                (false, true) => observer.on_sound_start(),
+                (true, false) => observer.on_sound_stop(),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// Writes down every callback it gets
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn on_instruction(&mut self, pc: u16, opcode: u16) {
+            self.0.borrow_mut().push(format!("{pc:03x} {opcode:04x}"));
+        }
+
+        fn on_memory_write(&mut self, address: u16, old: u8, new: u8) {
+            self.0
+                .borrow_mut()
+                .push(format!("write {address:03x} {old} {new}"));
+        }
+
+        fn on_draw(&mut self, x: usize, y: usize, rows: usize, collided: bool) {
+            self.0
+                .borrow_mut()
+                .push(format!("draw {x} {y} {rows} {collided}"));
+        }
+
+        fn on_sound_start(&mut self) {
+            self.0.borrow_mut().push("sound on".to_string());
+        }
+
+        fn on_sound_stop(&mut self) {
+            self.0.borrow_mut().push("sound off".to_string());
+        }
+
+        fn on_frame(&mut self, _frame: &Framebuffer) {
+            self.0.borrow_mut().push("frame".to_string());
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_everything() {
+        // 0x200: 6042 (V0 = 0x42), 0x202: A300, 0x204: F055, 0x206: D005
+        let mut cpu = CPU::new_with_memory(&[0x60, 0x42, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x05]);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        cpu.observer = Some(Box::new(Recorder(events.clone())));
+
+        for _ in 0..4 {
+            cpu.step();
+        }
+        cpu.set_sound_timer(1);
+        cpu.end_frame();
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                "200 6042",
+                "202 a300",
+                "204 f055",
+                "write 300 0 66",
+                "206 d005",
+                "draw 2 2 5 false",
+                "sound on",
+                "sound off",
+                "frame",
+            ]
+        );
+    }
+}
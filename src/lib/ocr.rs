@@ -0,0 +1,265 @@
+use super::cpu::CPU;
+use super::display::{HEIGHT, WIDTH};
+
+/// The hex digits in font order
+const DIGITS: [char; 16] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F',
+];
+
+/// A font glyph found on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    /// The column of the glyph's top left corner
+    pub x: usize,
+
+    /// The row of the glyph's top left corner
+    pub y: usize,
+
+    /// The hex digit the glyph shows
+    pub ch: char,
+
+    /// Whether this is an 8x10 SUPER-CHIP glyph rather than a 4x5 one
+    pub big: bool,
+}
+
+impl Glyph {
+    /// The width of the glyph in pixels, not counting the blank columns after it
+    pub fn width(&self) -> usize {
+        if self.big {
+            8
+        } else {
+            4
+        }
+    }
+}
+
+/// Glyphs of the same size that sit next to each other on the same row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextRun {
+    /// The column of the first glyph
+    pub x: usize,
+
+    /// The row the glyphs start on
+    pub y: usize,
+
+    /// The digits shown
+    pub text: String,
+
+    /// Whether the run is made of 8x10 SUPER-CHIP glyphs
+    pub big: bool,
+}
+
+impl CPU {
+    /// Finds every glyph of the machine's font on the screen, ordered top to bottom and
+    /// left to right.
+    ///
+    /// A glyph only counts if the pixels right around it are unlit, which keeps shapes
+    /// that merely contain a glyph from being recognised as text.
+    pub fn recognize_glyphs(&self) -> Vec<Glyph> {
+        let big = self.find_glyphs(&self.machine.font.big, 8, true);
+
+        // Parts of a big glyph can look like a small one
+        let small: Vec<Glyph> = self
+            .find_glyphs(&self.machine.font.small, 4, false)
+            .into_iter()
+            .filter(|glyph| {
+                !big.iter().any(|big| {
+                    (big.x..big.x + 8).contains(&glyph.x) && (big.y..big.y + 10).contains(&glyph.y)
+                })
+            })
+            .collect();
+
+        let mut glyphs = [big, small].concat();
+        glyphs.sort_by_key(|glyph| (glyph.y, glyph.x));
+        glyphs
+    }
+
+    /// Groups the recognised glyphs into runs of text
+    pub fn recognize_text(&self) -> Vec<TextRun> {
+        let mut runs: Vec<TextRun> = Vec::new();
+        let mut last_end = 0;
+
+        for glyph in self.recognize_glyphs() {
+            match runs.last_mut() {
+                // Allow up to a glyph's width of space between neighbours
+                Some(run)
+                    if run.y == glyph.y
+                        && run.big == glyph.big
+                        && glyph.x >= last_end
+                        && glyph.x <= last_end + glyph.width() =>
+                {
+                    run.text.push(glyph.ch);
+                }
+                _ => runs.push(TextRun {
+                    x: glyph.x,
+                    y: glyph.y,
+                    text: glyph.ch.to_string(),
+                    big: glyph.big,
+                }),
+            }
+            last_end = glyph.x + glyph.width();
+        }
+
+        runs
+    }
+
+    /// All recognised text, runs on the same row joined by spaces and rows by newlines
+    pub fn screen_text(&self) -> String {
+        let mut lines: Vec<(usize, Vec<String>)> = Vec::new();
+
+        for run in self.recognize_text() {
+            match lines.last_mut() {
+                Some((y, line)) if *y == run.y => line.push(run.text),
+                _ => lines.push((run.y, vec![run.text])),
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(_, line)| line.join(" "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Looks for every glyph of a font at every position on the screen
+    fn find_glyphs(&self, font: &[u8], width: usize, big: bool) -> Vec<Glyph> {
+        let height = font.len() / DIGITS.len();
+        let mut glyphs = Vec::new();
+
+        for (rows, ch) in font.chunks(height).zip(DIGITS) {
+            for y in 0..=(HEIGHT as usize - height) {
+                for x in 0..=(WIDTH as usize - width) {
+                    if self.glyph_at(x, y, rows, width) {
+                        glyphs.push(Glyph { x, y, ch, big });
+                    }
+                }
+            }
+        }
+
+        glyphs
+    }
+
+    /// Checks a glyph against the screen, including a one pixel unlit border around it
+    fn glyph_at(&self, x: usize, y: usize, rows: &[u8], width: usize) -> bool {
+        for dy in -1..=rows.len() as isize {
+            for dx in -1..=width as isize {
+                let expected = match rows.get(dy as usize) {
+                    Some(row) if dy >= 0 && (0..width as isize).contains(&dx) => {
+                        (row >> (7 - dx)) & 1 == 1
+                    }
+                    _ => false,
+                };
+
+                if self.pixel_lit(x as isize + dx, y as isize + dy) != expected {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Whether a pixel is lit, anything off the screen counts as unlit
+    fn pixel_lit(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && self.buf.get(x as usize, y as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::BIG_FONT;
+    use crate::font::FontSet;
+    use crate::machine::MachineConfig;
+
+    /// Draws a small font digit at a position with Fx29 and Dxy5
+    fn draw_digit(cpu: &mut CPU, digit: u8, x: u8, y: u8) {
+        cpu.set6xnn(0, digit);
+        cpu.set6xnn(1, x);
+        cpu.set6xnn(2, y);
+        cpu.ldfx29(0);
+        cpu.drwdxyn(1, 2, 5);
+    }
+
+    #[test]
+    fn test_recognize_small_digits() {
+        let mut cpu = CPU::new();
+
+        draw_digit(&mut cpu, 0x1, 10, 3);
+        draw_digit(&mut cpu, 0xA, 15, 3);
+        draw_digit(&mut cpu, 0x3, 20, 3);
+        draw_digit(&mut cpu, 0x8, 40, 20);
+
+        assert_eq!(
+            cpu.recognize_text(),
+            vec![
+                TextRun {
+                    x: 10,
+                    y: 3,
+                    text: "1A3".to_string(),
+                    big: false
+                },
+                TextRun {
+                    x: 40,
+                    y: 20,
+                    text: "8".to_string(),
+                    big: false
+                },
+            ]
+        );
+        assert_eq!(cpu.screen_text(), "1A3\n8");
+    }
+
+    #[test]
+    fn test_recognize_big_digit() {
+        let mut cpu = CPU::new();
+
+        // Big glyph for 7
+        cpu.bus.load(0x300, &BIG_FONT[70..80]);
+        cpu.setannn(0x300);
+        cpu.set6xnn(1, 30);
+        cpu.set6xnn(2, 10);
+        cpu.drwdxyn(1, 2, 10);
+
+        let glyphs = cpu.recognize_glyphs();
+        assert_eq!(
+            glyphs,
+            vec![Glyph {
+                x: 30,
+                y: 10,
+                ch: '7',
+                big: true
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recognize_the_machines_font() {
+        let machine = MachineConfig {
+            font: FontSet::eti660(),
+            ..MachineConfig::chip8()
+        };
+        let mut cpu = CPU::with_machine(machine, &[]).unwrap();
+
+        draw_digit(&mut cpu, 0x2, 10, 3);
+        draw_digit(&mut cpu, 0xC, 15, 3);
+        assert_eq!(cpu.screen_text(), "2C");
+
+        // The same digits drawn with the default font aren't the machine's glyphs
+        let mut other = CPU::new();
+        draw_digit(&mut other, 0x2, 10, 3);
+        draw_digit(&mut other, 0xC, 15, 3);
+        cpu.buf = other.buf.clone();
+        assert_eq!(cpu.screen_text(), "");
+    }
+
+    #[test]
+    fn test_touching_pixels_are_not_text() {
+        let mut cpu = CPU::new();
+
+        draw_digit(&mut cpu, 0x5, 10, 10);
+        cpu.buf.set(11, 9, true);
+
+        assert!(cpu.recognize_glyphs().is_empty());
+    }
+}
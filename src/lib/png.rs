@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+
+/// An RGB colour
+pub type Rgb = [u8; 3];
+
+/// Black and white for plain CHIP-8, plus two greys for the extra bitplane colours
+pub const DEFAULT_PALETTE: [Rgb; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+/// The largest payload a single stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// The CRC-32 lookup table used by PNG chunks
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// Settings for writing a PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngOptions {
+    /// Every pixel becomes a `scale` x `scale` block
+    pub scale: u32,
+
+    /// The colour for each pixel value. Plain CHIP-8 only uses the first two,
+    /// the other two are for pixels that combine two bitplanes like XO-CHIP does
+    pub palette: [Rgb; 4],
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            scale: 8,
+            palette: DEFAULT_PALETTE,
+        }
+    }
+}
+
+/// Encodes a frame of palette indices as an indexed colour PNG
+pub fn encode<W: Write>(
+    out: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    options: &PngOptions,
+) -> io::Result<()> {
+    let scale = options.scale.max(1) as usize;
+    let (scaled_width, scaled_height) = (width * scale, height * scale);
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(scaled_width as u32).to_be_bytes());
+    header.extend_from_slice(&(scaled_height as u32).to_be_bytes());
+    // 8 bits per pixel, indexed colour, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    write_chunk(out, b"PLTE", options.palette.as_flattened())?;
+
+    // Every scanline starts with its filter type, which is always 0 (none) here
+    let mut scanlines = Vec::with_capacity((scaled_width + 1) * scaled_height);
+    for row in pixels.chunks(width) {
+        let mut scanline = Vec::with_capacity(scaled_width + 1);
+        scanline.push(0);
+        for pixel in row {
+            scanline.extend(std::iter::repeat_n((*pixel).min(3), scale));
+        }
+
+        for _ in 0..scale {
+            scanlines.extend_from_slice(&scanline);
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Writes one chunk with its length and CRC
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32_update(crc32_update(0xFFFF_FFFF, kind), data) ^ 0xFFFF_FFFF;
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps the data in a zlib stream made of uncompressed deflate blocks
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let block_count = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + block_count * 5 + 6);
+
+    // Deflate with a 32K window and the lowest compression level
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(is_final as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// The CRC-32 checksum PNG uses for its chunks
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0xFFFF_FFFF, data) ^ 0xFFFF_FFFF
+}
+
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The Adler-32 checksum zlib puts at the end of a stream
+pub fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn test_zlib_stored_splits_blocks() {
+        let data = vec![7; MAX_STORED_BLOCK + 10];
+        let stream = zlib_stored(&data);
+
+        // Header, two block headers, the data and the checksum
+        assert_eq!(stream.len(), 2 + 5 + 5 + data.len() + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + MAX_STORED_BLOCK], 1);
+    }
+
+    #[test]
+    fn test_encode_header() {
+        let options = PngOptions {
+            scale: 3,
+            ..PngOptions::default()
+        };
+        let mut out = Vec::new();
+        encode(&mut out, 4, 2, &[0, 1, 1, 0, 1, 0, 0, 1], &options).unwrap();
+
+        assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&out[12..16], b"IHDR");
+        assert_eq!(&out[16..20], &12u32.to_be_bytes());
+        assert_eq!(&out[20..24], &6u32.to_be_bytes());
+        assert_eq!(&out[out.len() - 8..out.len() - 4], b"IEND");
+    }
+}
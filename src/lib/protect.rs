@@ -0,0 +1,463 @@
+use std::str::FromStr;
+
+use super::cpu::CPU;
+use super::font::{BIG_FONT_SIZE, SMALL_FONT_SIZE};
+
+/// The most diagnostics a run keeps, a loop doing the same thing wrong would otherwise
+/// fill memory
+pub const MAX_DIAGNOSTICS: usize = 256;
+
+/// The last address of the 4 KiB classic CHIP-8 could reach
+const MAX_CLASSIC_ADDRESS: usize = 0xFFF;
+
+/// What to do when a program touches memory it probably shouldn't
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protection {
+    /// Don't look
+    #[default]
+    Off,
+
+    /// Record a [`Diagnostic`] and carry on as if nothing happened
+    Warn,
+
+    /// Record a [`Diagnostic`], drop writes to the interpreter area, the font and past the
+    /// end of memory, and read 0 past the end of memory and from memory past the ROM that
+    /// the program never wrote. I is left alone, there is nothing sensible to replace it
+    /// with.
+    Block,
+}
+
+impl FromStr for Protection {
+    type Err = String;
+
+    /// Parses `off`, `warn` or `block`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "off" => Ok(Protection::Off),
+            "warn" => Ok(Protection::Warn),
+            "block" => Ok(Protection::Block),
+            _ => Err(format!(
+                "protection must be off, warn or block, got `{value}`"
+            )),
+        }
+    }
+}
+
+/// A suspicious memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// Fx33 or Fx55 wrote below the load address or into the font
+    ReservedWrite { address: u16, blocked: bool },
+
+    /// Fx65 or Dxyn read past the end of the ROM image, from memory nothing wrote to,
+    /// reading 0 instead if blocked
+    ReadPastRom { address: u16, blocked: bool },
+
+    /// Fx1E moved I past 0xFFF or the end of memory, `i` being the sum before it wrapped
+    IndexOverflow { i: usize },
+
+    /// Fx33 or Fx55 wrote past the end of memory, wrapping around to the start unless
+    /// blocked
+    WritePastMemory { address: usize, blocked: bool },
+
+    /// Fx65 or Dxyn read past the end of memory, wrapping around to the start unless
+    /// blocked
+    ReadPastMemory { address: usize, blocked: bool },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::ReservedWrite {
+                address,
+                blocked: true,
+            } => write!(
+                f,
+                "blocked a write to the interpreter area at {address:#05x}"
+            ),
+            Violation::ReservedWrite { address, .. } => {
+                write!(f, "wrote to the interpreter area at {address:#05x}")
+            }
+            Violation::ReadPastRom {
+                address,
+                blocked: true,
+            } => write!(
+                f,
+                "blocked a read of uninitialised memory past the ROM at {address:#05x}"
+            ),
+            Violation::ReadPastRom { address, .. } => {
+                write!(
+                    f,
+                    "read uninitialised memory past the ROM at {address:#05x}"
+                )
+            }
+            Violation::IndexOverflow { i } => write!(f, "I overflowed to {i:#05x}"),
+            Violation::WritePastMemory {
+                address,
+                blocked: true,
+            } => write!(
+                f,
+                "blocked a write past the end of memory at {address:#05x}"
+            ),
+            Violation::WritePastMemory { address, .. } => {
+                write!(f, "wrote past the end of memory at {address:#05x}")
+            }
+            Violation::ReadPastMemory {
+                address,
+                blocked: true,
+            } => write!(f, "blocked a read past the end of memory at {address:#05x}"),
+            Violation::ReadPastMemory { address, .. } => {
+                write!(f, "read past the end of memory at {address:#05x}")
+            }
+        }
+    }
+}
+
+/// A [`Violation`] and the instruction that caused it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pc: u16,
+    pub opcode: u16,
+    pub violation: Violation,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#05x} ({:04x}): {}",
+            self.pc, self.opcode, self.violation
+        )
+    }
+}
+
+impl CPU {
+    /// Writes a byte of memory for Fx33 or Fx55, checking it under the current
+    /// [`Protection`]. `address` is I plus the offset, it wraps around the end of memory
+    /// unless that is blocked.
+    pub fn write_data(&mut self, address: usize, value: u8) {
+        if address >= self.bus.size() && self.protection != Protection::Off {
+            let blocked = self.protection == Protection::Block;
+            self.diagnose(Violation::WritePastMemory { address, blocked });
+            if blocked {
+                return;
+            }
+        }
+
+        let address = self.wrap_address(address);
+        if self.protection == Protection::Off {
+            self.write_memory(address, value);
+            return;
+        }
+
+        if self.is_reserved(address) {
+            let blocked = self.protection == Protection::Block;
+            self.diagnose(Violation::ReservedWrite { address, blocked });
+            if blocked {
+                return;
+            }
+        }
+
+        if address as usize >= self.rom_end() {
+            self.written_past_rom.insert(address);
+        }
+        self.write_memory(address, value);
+    }
+
+    /// Reads a byte of memory for Fx65 or Dxyn, checking it under the current
+    /// [`Protection`]. `address` is I plus the offset, it wraps around the end of memory.
+    /// Blocked reads, past the end of memory or of uninitialised memory past the ROM,
+    /// return 0.
+    pub fn read_data(&mut self, address: usize) -> u8 {
+        if address >= self.bus.size() && self.protection != Protection::Off {
+            let blocked = self.protection == Protection::Block;
+            self.diagnose(Violation::ReadPastMemory { address, blocked });
+            if blocked {
+                return 0;
+            }
+        }
+
+        let address = self.wrap_address(address);
+        if self.protection != Protection::Off
+            && address as usize >= self.rom_end()
+            && !self.written_past_rom.contains(&address)
+        {
+            let blocked = self.protection == Protection::Block;
+            self.diagnose(Violation::ReadPastRom { address, blocked });
+            if blocked {
+                return 0;
+            }
+        }
+
+        self.bus.read(address)
+    }
+
+    /// Checks the sum Fx1E is about to store in I. It overflows when it carries out of
+    /// 16 bits, or when it moves I from at or below 0xFFF, or the end of a smaller
+    /// memory, to past it.
+    pub fn check_index(&mut self, sum: usize) {
+        if self.protection == Protection::Off {
+            return;
+        }
+
+        let bound = MAX_CLASSIC_ADDRESS.min(self.bus.size() - 1);
+        let crossed = self.i_reg as usize <= bound && sum > bound;
+        if sum > u16::MAX as usize || crossed {
+            self.diagnose(Violation::IndexOverflow { i: sum });
+        }
+    }
+
+    /// Whether `address` belongs to the interpreter: below the load address, or in the font
+    fn is_reserved(&self, address: u16) -> bool {
+        let font_start = self.machine.font_address as usize;
+        let font = font_start..font_start + SMALL_FONT_SIZE + BIG_FONT_SIZE;
+
+        address < self.machine.load_address || font.contains(&(address as usize))
+    }
+
+    /// The first address after the loaded ROM
+    fn rom_end(&self) -> usize {
+        self.machine.load_address as usize + self.rom_size
+    }
+
+    /// Records a violation by the instruction being executed, skipping repeats
+    fn diagnose(&mut self, violation: Violation) {
+        // The instruction has been fetched, so pc already points past it
+        let pc = self.pc.wrapping_sub(2);
+        let diagnostic = Diagnostic {
+            pc,
+            opcode: self.opcode_at(pc).unwrap_or_default(),
+            violation,
+        };
+
+        if self.diagnostics.len() >= MAX_DIAGNOSTICS || self.diagnostics.contains(&diagnostic) {
+            return;
+        }
+        if let Some(observer) = &mut self.observer {
+            observer.on_diagnostic(&diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::FontSet;
+    use crate::halt::RunStatus;
+    use crate::machine::MachineConfig;
+
+    #[test]
+    fn test_font_writes_are_blocked() {
+        // 0x200: A050 (I = the font), 0x202: 6012, 0x204: F055
+        let mut cpu = CPU::new_with_memory(&[0xA0, 0x50, 0x60, 0x12, 0xF0, 0x55]);
+        cpu.protection = Protection::Block;
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.bus.peek(0x050), FontSet::chip8().small[0]);
+        assert_eq!(
+            cpu.diagnostics,
+            [Diagnostic {
+                pc: 0x204,
+                opcode: 0xF055,
+                violation: Violation::ReservedWrite {
+                    address: 0x050,
+                    blocked: true
+                },
+            }]
+        );
+        assert_eq!(
+            cpu.diagnostics[0].to_string(),
+            "0x204 (f055): blocked a write to the interpreter area at 0x050"
+        );
+    }
+
+    #[test]
+    fn test_warnings_let_writes_through() {
+        // 0x200: A100, 0x202: 60FF, 0x204: F033
+        let mut cpu = CPU::new_with_memory(&[0xA1, 0x00, 0x60, 0xFF, 0xF0, 0x33]);
+        cpu.protection = Protection::Warn;
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.bus.peek(0x100), 2);
+        // One diagnostic per byte written
+        assert_eq!(cpu.diagnostics.len(), 3);
+    }
+
+    #[test]
+    fn test_reads_past_the_rom() {
+        // 0x200: A300, 0x202: F065, 0x204: F055, 0x206: F065
+        let mut cpu = CPU::new_with_memory(&[0xA3, 0x00, 0xF0, 0x65, 0xF0, 0x55, 0xF0, 0x65]);
+        cpu.protection = Protection::Warn;
+
+        for _ in 0..4 {
+            cpu.step();
+        }
+
+        // Reading back what the program stored itself is fine
+        assert_eq!(
+            cpu.diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.pc, diagnostic.violation))
+                .collect::<Vec<_>>(),
+            [(
+                0x202,
+                Violation::ReadPastRom {
+                    address: 0x300,
+                    blocked: false
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_reads_past_the_rom_are_blocked() {
+        // 0x200: A300, 0x202: F065, 0x204: 6107, 0x206: F155, 0x208: F165
+        let rom = [0xA3, 0x00, 0xF0, 0x65, 0x61, 0x07, 0xF1, 0x55, 0xF1, 0x65];
+        let mut cpu = CPU::new_with_memory(&rom);
+        cpu.protection = Protection::Block;
+        cpu.bus.write(0x300, 0x42);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers[0], 0);
+        assert_eq!(
+            cpu.diagnostics[0].to_string(),
+            "0x202 (f065): blocked a read of uninitialised memory past the ROM at 0x300"
+        );
+
+        // What the program stored itself reads back
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers[..2], [0, 7]);
+        assert_eq!(cpu.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_index_overflow() {
+        // 0x200: AFFF, 0x202: 6002, 0x204: F01E
+        let mut cpu = CPU::new_with_memory(&[0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E]);
+        cpu.protection = Protection::Warn;
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+
+        assert_eq!(
+            cpu.diagnostics[0].violation,
+            Violation::IndexOverflow { i: 0x1001 }
+        );
+    }
+
+    #[test]
+    fn test_accesses_past_memory_after_an_overflow() {
+        // 0x200: AFFF, 0x202: 6002, 0x204: F01E (I = 0x1001), 0x206: 6134,
+        // 0x208: F155, 0x20A: F165
+        let rom = [
+            0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E, 0x61, 0x34, 0xF1, 0x55, 0xF1, 0x65,
+        ];
+
+        let mut cpu = CPU::new_with_memory(&rom);
+        cpu.protection = Protection::Block;
+        for _ in 0..6 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.status, RunStatus::Running);
+        assert_eq!((cpu.bus.peek(0x001), cpu.bus.peek(0x002)), (0, 0));
+        assert_eq!(cpu.registers[..2], [0, 0]);
+        assert_eq!(
+            cpu.diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.pc, diagnostic.violation))
+                .collect::<Vec<_>>(),
+            [
+                (0x204, Violation::IndexOverflow { i: 0x1001 }),
+                (
+                    0x208,
+                    Violation::WritePastMemory {
+                        address: 0x1001,
+                        blocked: true
+                    }
+                ),
+                (
+                    0x208,
+                    Violation::WritePastMemory {
+                        address: 0x1002,
+                        blocked: true
+                    }
+                ),
+                (
+                    0x20A,
+                    Violation::ReadPastMemory {
+                        address: 0x1001,
+                        blocked: true
+                    }
+                ),
+                (
+                    0x20A,
+                    Violation::ReadPastMemory {
+                        address: 0x1002,
+                        blocked: true
+                    }
+                ),
+            ]
+        );
+
+        // Without protection the accesses wrap around to the start of memory
+        let mut cpu = CPU::new_with_memory(&rom);
+        for _ in 0..6 {
+            cpu.step();
+        }
+
+        assert_eq!((cpu.bus.peek(0x001), cpu.bus.peek(0x002)), (0x02, 0x34));
+        assert_eq!(cpu.registers[..2], [0x02, 0x34]);
+        assert!(cpu.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_index_overflow_on_64k() {
+        // 0x200: AFFF, 0x202: 6002, 0x204: F01E (I = 0x1001)
+        let rom = [0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E];
+        let mut cpu = CPU::with_machine(MachineConfig::xochip(), &rom).unwrap();
+        cpu.protection = Protection::Warn;
+        for _ in 0..3 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.i_reg, 0x1001);
+        assert_eq!(
+            cpu.diagnostics[0].violation,
+            Violation::IndexOverflow { i: 0x1001 }
+        );
+
+        // Carrying out of 16 bits wraps I to a low address, which is still an overflow
+        cpu.diagnostics.clear();
+        cpu.i_reg = 0xFFFF;
+        cpu.addfx1e(0);
+        assert_eq!(cpu.i_reg, 0x0001);
+        assert_eq!(
+            cpu.diagnostics[0].violation,
+            Violation::IndexOverflow { i: 0x10001 }
+        );
+
+        // Moving about above 0xFFF is what 64 KiB is for
+        cpu.diagnostics.clear();
+        cpu.i_reg = 0x2000;
+        cpu.addfx1e(0);
+        assert!(cpu.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_parse_protection() {
+        assert_eq!("block".parse(), Ok(Protection::Block));
+        assert!("strict".parse::<Protection>().is_err());
+    }
+}
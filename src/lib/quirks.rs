@@ -0,0 +1,37 @@
+use std::str::FromStr;
+
+/// Behaviours that differ between CHIP-8 interpreters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vx in place and ignore Vy, like SUPER-CHIP does
+    pub shift: bool,
+
+    /// Sprite pixels past the right or bottom edge wrap around to the other side instead
+    /// of being clipped
+    pub wrap: bool,
+
+    /// Dxyn stalls the CPU until the next 60 Hz frame boundary, like the COSMAC VIP
+    /// waiting for its display interrupt, so at most one sprite is drawn per frame
+    pub vblank: bool,
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    /// Parses a comma separated list of quirk names, `none` for the defaults
+    fn from_str(names: &str) -> Result<Self, Self::Err> {
+        let mut quirks = Quirks::default();
+
+        for name in names.split(',').map(str::trim) {
+            match name {
+                "" | "none" => {}
+                "shift" => quirks.shift = true,
+                "wrap" => quirks.wrap = true,
+                "vblank" => quirks.vblank = true,
+                _ => return Err(format!("unknown quirk `{name}`")),
+            }
+        }
+
+        Ok(quirks)
+    }
+}
@@ -0,0 +1,57 @@
+use std::io;
+
+use super::framebuffer::Framebuffer;
+
+/// Receives every emulated frame at the 60 Hz frame boundary, after the timers have ticked
+pub trait FrameSink {
+    /// Called once per frame with the screen and whether the sound timer is running
+    fn present(&mut self, frame: &Framebuffer, sound: bool) -> io::Result<()>;
+
+    /// Called once when the run is over, flushes anything still buffered
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The sinks attached to a machine, remembering the first error any of them ran into so a
+/// failing sink doesn't stop the emulation
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<Box<dyn FrameSink>>,
+
+    /// The first error a sink returned, reported by [`Sinks::finish`]
+    error: Option<io::Error>,
+}
+
+impl Sinks {
+    pub fn push(&mut self, sink: Box<dyn FrameSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Hands a finished frame to every sink
+    pub fn present(&mut self, frame: &Framebuffer, sound: bool) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.present(frame, sound) {
+                self.error.get_or_insert(err);
+            }
+        }
+    }
+
+    /// Finishes and detaches all sinks, returning the first error any of them ran into
+    pub fn finish(&mut self) -> io::Result<()> {
+        for mut sink in self.sinks.drain(..) {
+            if let Err(err) = sink.finish() {
+                self.error.get_or_insert(err);
+            }
+        }
+
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
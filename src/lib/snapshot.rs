@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+
+use super::cpu::CPU;
+use super::emulator::Emulator;
+use super::input::InputScript;
+
+/// Set this environment variable to rewrite golden files instead of comparing against them
+pub const UPDATE_ENV: &str = "CHIP8_UPDATE_SNAPSHOTS";
+
+/// Loads a ROM and runs it for a number of frames with scripted input
+pub fn run_rom(rom: &[u8], frames: usize, input: &InputScript) -> CPU {
+    let mut cpu = CPU::new_with_memory(rom);
+    cpu.run_scripted(frames, input);
+    cpu
+}
+
+/// The display buffer in the same text format golden files use
+pub fn render_ascii(cpu: &CPU) -> String {
+    let mut out = Vec::new();
+    cpu.buf
+        .write_ascii(&mut out)
+        .expect("writing to a Vec cannot fail");
+
+    String::from_utf8(out).expect("the text format is ASCII")
+}
+
+/// Compares the display buffer with a golden file, returning a visual diff if they differ.
+///
+/// If [`UPDATE_ENV`] is set the golden file is written instead.
+pub fn check_snapshot(cpu: &CPU, golden: &Path) -> Result<(), String> {
+    let actual = render_ascii(cpu);
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        if let Some(parent) = golden.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        return fs::write(golden, actual)
+            .map_err(|err| format!("could not write {}: {err}", golden.display()));
+    }
+
+    let expected = fs::read_to_string(golden).map_err(|err| {
+        format!(
+            "could not read {}: {err}\nrun with {UPDATE_ENV}=1 to create it",
+            golden.display()
+        )
+    })?;
+
+    if expected.lines().eq(actual.lines()) {
+        return Ok(());
+    }
+
+    Err(format!(
+        "display does not match {}\nrun with {UPDATE_ENV}=1 to accept the new output\n\n{}",
+        golden.display(),
+        visual_diff(&expected, &actual)
+    ))
+}
+
+/// Panics with a visual diff if the display buffer does not match the golden file
+pub fn assert_snapshot(cpu: &CPU, golden: &Path) {
+    if let Err(message) = check_snapshot(cpu, golden) {
+        panic!("{message}");
+    }
+}
+
+/// Shows the expected and actual frames side by side with a third column marking pixels
+/// that are only lit in the actual frame with `+` and only in the expected frame with `-`
+pub fn visual_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let width = expected.iter().chain(&actual).map(|line| line.len()).max();
+    let width = width.unwrap_or(0);
+
+    let mut out = format!("  {:width$} | {:width$} | diff\n", "expected", "actual");
+    let mut different_pixels = 0;
+
+    for row in 0..expected.len().max(actual.len()) {
+        let expected_row = expected.get(row).copied().unwrap_or("");
+        let actual_row = actual.get(row).copied().unwrap_or("");
+
+        let mut expected_pixels = expected_row.chars();
+        let mut actual_pixels = actual_row.chars();
+        let diff: String = (0..width)
+            .map(|_| {
+                let expected_lit = expected_pixels.next() == Some('#');
+                let actual_lit = actual_pixels.next() == Some('#');
+                match (expected_lit, actual_lit) {
+                    (false, true) => '+',
+                    (true, false) => '-',
+                    _ => '.',
+                }
+            })
+            .collect();
+
+        let changed = diff.chars().filter(|pixel| *pixel != '.').count();
+        different_pixels += changed;
+
+        let marker = if changed > 0 || expected_row.len() != actual_row.len() {
+            '>'
+        } else {
+            ' '
+        };
+        out += &format!("{marker} {expected_row:width$} | {actual_row:width$} | {diff}\n");
+    }
+
+    out += &format!("{different_pixels} pixels differ\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn golden(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("snapshots")
+            .join(name)
+    }
+
+    #[test]
+    fn test_visual_diff() {
+        let diff = visual_diff("#.\n..\n", "##\n..\n");
+        let lines: Vec<&str> = diff.lines().collect();
+
+        assert_eq!(lines[1], "> #. | ## | .+");
+        assert_eq!(lines[2], "  .. | .. | ..");
+        assert_eq!(lines[3], "1 pixels differ");
+    }
+
+    #[test]
+    fn snapshot_ibmlogo() {
+        let cpu = run_rom(
+            include_bytes!("../../ibmlogo.ch8"),
+            20,
+            &InputScript::default(),
+        );
+        assert_snapshot(&cpu, &golden("ibmlogo.txt"));
+    }
+
+    #[test]
+    fn snapshot_test_opcode() {
+        let cpu = run_rom(
+            include_bytes!("../../test_opcode.ch8"),
+            60,
+            &InputScript::default(),
+        );
+        assert_snapshot(&cpu, &golden("test_opcode.txt"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::str::FromStr;
+
+use super::cpu::CPU;
+use super::halt::{Fault, RunStatus};
+
+/// The bytes at the top of RAM the VIP interpreter keeps its call stack, variables and
+/// the display page in. The stack starts at the bottom of it, 0xEA0 in 4 KiB.
+pub const VIP_WORK_AREA: usize = 0x160;
+
+/// Where the VIP interpreter keeps its call stack in `memory_size` bytes of RAM
+pub fn vip_stack_base(memory_size: usize) -> usize {
+    memory_size.saturating_sub(VIP_WORK_AREA)
+}
+
+/// How deep subroutine calls may nest and where the return addresses are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackConfig {
+    /// The most return addresses the stack holds, `None` for no limit
+    pub max_depth: Option<usize>,
+
+    /// If set, return addresses are stored big endian in emulated memory where the VIP
+    /// keeps them, see [`vip_stack_base`], so programs can see and overwrite them
+    pub in_memory: bool,
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        StackConfig::schip()
+    }
+}
+
+impl StackConfig {
+    /// 12 levels kept in memory, like the COSMAC VIP interpreter
+    pub fn vip() -> Self {
+        StackConfig {
+            max_depth: Some(12),
+            in_memory: true,
+        }
+    }
+
+    /// 16 levels, like SUPER-CHIP
+    pub fn schip() -> Self {
+        StackConfig {
+            max_depth: Some(16),
+            in_memory: false,
+        }
+    }
+
+    /// No limit, handy while developing a ROM
+    pub fn unlimited() -> Self {
+        StackConfig {
+            max_depth: None,
+            in_memory: false,
+        }
+    }
+}
+
+impl FromStr for StackConfig {
+    type Err = String;
+
+    /// Parses `vip`, `schip`, `unlimited` or a maximum depth
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "vip" => Ok(StackConfig::vip()),
+            "schip" => Ok(StackConfig::schip()),
+            "unlimited" => Ok(StackConfig::unlimited()),
+            depth => match depth.parse() {
+                Ok(depth) => Ok(StackConfig {
+                    max_depth: Some(depth),
+                    in_memory: false,
+                }),
+                Err(_) => Err(format!("unknown stack `{value}`")),
+            },
+        }
+    }
+}
+
+impl CPU {
+    /// Pushes a return address, faulting if the stack is full
+    pub fn push_return(&mut self, address: u16) -> Result<(), Fault> {
+        if self
+            .stack_config
+            .max_depth
+            .is_some_and(|max| self.sp >= max)
+        {
+            return Err(Fault::StackOverflow { depth: self.sp });
+        }
+
+        match self.stack_base() {
+            Some(base) => {
+                let slot = base + self.sp * 2;
+                if slot + 2 > self.bus.size() {
+                    return Err(Fault::StackOverflow { depth: self.sp });
+                }
+                let [high, low] = address.to_be_bytes();
+                self.write_memory(slot as u16, high);
+                self.write_memory(slot as u16 + 1, low);
+            }
+            None => {
+                self.stack.truncate(self.sp);
+                self.stack.push(address);
+            }
+        }
+
+        self.sp += 1;
+        Ok(())
+    }
+
+    /// Pops the most recent return address, faulting if the stack is empty
+    pub fn pop_return(&mut self) -> Result<u16, Fault> {
+        if self.sp == 0 {
+            return Err(Fault::StackUnderflow);
+        }
+        self.sp -= 1;
+
+        let address = self.return_address(self.sp);
+        if !self.stack_config.in_memory {
+            self.stack.truncate(self.sp);
+        }
+
+        Ok(address)
+    }
+
+    /// The return addresses of the calls that haven't returned yet, innermost first
+    pub fn call_stack(&self) -> Vec<u16> {
+        (0..self.sp)
+            .rev()
+            .map(|depth| self.return_address(depth))
+            .collect()
+    }
+
+    /// The return address at a depth, 0 being the outermost call
+    fn return_address(&self, depth: usize) -> u16 {
+        match self.stack_base() {
+            Some(base) => {
+                let slot = base + depth * 2;
+                if slot + 2 > self.bus.size() {
+                    return 0;
+                }
+                u16::from_be_bytes([self.bus.peek(slot as u16), self.bus.peek(slot as u16 + 1)])
+            }
+            None => self.stack.get(depth).copied().unwrap_or_default(),
+        }
+    }
+
+    /// Where return addresses go in memory, if they are kept there
+    fn stack_base(&self) -> Option<usize> {
+        self.stack_config
+            .in_memory
+            .then(|| vip_stack_base(self.bus.size()))
+    }
+
+    /// Stops at the instruction that just ran, which began at `pc - 2`, so it runs again
+    /// and faults the same way if the program keeps going
+    pub fn fault(&mut self, fault: Fault) {
+        self.rewind_pc();
+        self.status = RunStatus::Faulted { pc: self.pc, fault };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineConfig;
+
+    #[test]
+    fn test_call_pushes_the_return_address() {
+        // 0x200: 2206 (call 0x206), 0x202: 1202, 0x204: 0000, 0x206: 00EE
+        let mut cpu = CPU::new_with_memory(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE]);
+
+        cpu.step();
+        assert_eq!(cpu.pc, 0x206);
+        assert_eq!(cpu.call_stack(), vec![0x202]);
+
+        cpu.step();
+        assert_eq!(cpu.pc, 0x202);
+        assert!(cpu.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_overflow_and_underflow_fault() {
+        // 0x200: 2200, calling itself forever
+        let mut cpu = CPU::new_with_memory(&[0x22, 0x00]);
+        cpu.stack_config = "3".parse().unwrap();
+
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.sp, 3);
+        assert_eq!(
+            cpu.status,
+            RunStatus::Faulted {
+                pc: 0x200,
+                fault: Fault::StackOverflow { depth: 3 }
+            }
+        );
+
+        // 0x200: 00EE
+        let mut cpu = CPU::new_with_memory(&[0x00, 0xEE]);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(
+            cpu.status,
+            RunStatus::Faulted {
+                pc: 0x200,
+                fault: Fault::StackUnderflow
+            }
+        );
+    }
+
+    #[test]
+    fn test_vip_stack_lives_in_memory() {
+        let mut cpu = CPU::new();
+        cpu.stack_config = StackConfig::vip();
+
+        cpu.push_return(0x234).unwrap();
+        cpu.push_return(0x456).unwrap();
+        let bytes: Vec<u8> = (0xEA0..0xEA4)
+            .map(|address| cpu.bus.peek(address))
+            .collect();
+        assert_eq!(bytes, [0x02, 0x34, 0x04, 0x56]);
+        assert!(cpu.stack.is_empty());
+
+        // Programs can overwrite their own return addresses
+        cpu.bus.write(0xEA3, 0x60);
+        assert_eq!(cpu.pop_return(), Ok(0x460));
+        assert_eq!(cpu.pop_return(), Ok(0x234));
+        assert_eq!(cpu.pop_return(), Err(Fault::StackUnderflow));
+
+        for _ in 0..12 {
+            cpu.push_return(0x200).unwrap();
+        }
+        assert_eq!(
+            cpu.push_return(0x200),
+            Err(Fault::StackOverflow { depth: 12 })
+        );
+    }
+
+    #[test]
+    fn test_vip_stack_on_2k() {
+        // 0x200: 2206 (call 0x206), 0x202: 1202, 0x204: 0000, 0x206: 00EE
+        let program = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE];
+        let mut cpu = CPU::with_machine(MachineConfig::vip_2k(), &program).unwrap();
+        cpu.stack_config = StackConfig::vip();
+
+        assert_eq!(cpu.step(), RunStatus::Running);
+        assert_eq!(cpu.pc, 0x206);
+        assert_eq!((cpu.bus.peek(0x6A0), cpu.bus.peek(0x6A1)), (0x02, 0x02));
+
+        assert_eq!(cpu.step(), RunStatus::Running);
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.sp, 0);
+    }
+
+    #[test]
+    fn test_unlimited_stack() {
+        let mut cpu = CPU::new();
+        cpu.stack_config = StackConfig::unlimited();
+
+        for address in 0..100 {
+            cpu.push_return(address).unwrap();
+        }
+        assert_eq!(cpu.call_stack()[0], 99);
+    }
+}
@@ -0,0 +1,467 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cpu::CPU;
+use super::halt::RunStatus;
+use super::input::InputScript;
+use super::machine::MachineConfig;
+use super::quirks::Quirks;
+
+/// How many frames a test runs for when the manifest does not say
+pub const DEFAULT_FRAMES: usize = 60;
+
+/// What a test ROM should have done by the end of its run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expectation {
+    /// The display matches this [`CPU::framebuffer_hash`]
+    Hash(u32),
+
+    /// The recognised screen text contains this string
+    Text(String),
+
+    /// The byte at an address has this value
+    Memory { address: u16, value: u8 },
+}
+
+/// One ROM in a test manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    /// The ROM file, relative to the suite directory
+    pub rom: PathBuf,
+
+    /// The most frames to run, the test ends sooner if the ROM halts
+    pub frames: usize,
+
+    /// The quirks to run with
+    pub quirks: Quirks,
+
+    /// The memory layout to run on
+    pub machine: MachineConfig,
+
+    /// Key presses to replay
+    pub input: InputScript,
+
+    /// Every one of these has to hold for the test to pass
+    pub expect: Vec<Expectation>,
+}
+
+/// The outcome of running one [`TestCase`]
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// The ROM file the test ran
+    pub rom: PathBuf,
+
+    /// How long the run took
+    pub duration: Duration,
+
+    /// How many frames ran before the ROM halted or the frame limit was reached
+    pub frames: usize,
+
+    /// Whether the ROM had halted at the end of the run
+    pub status: Option<RunStatus>,
+
+    /// The hash of the final display, if the ROM ran to the end
+    pub hash: Option<u32>,
+
+    /// The recognised screen text, if the ROM ran to the end
+    pub text: Option<String>,
+
+    /// Why the test failed, empty if it passed
+    pub failures: Vec<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Parses a test manifest.
+///
+/// Every ROM gets a `[file.ch8]` section followed by `key = value` lines:
+///
+/// ```text
+/// [ibmlogo.ch8]
+/// frames = 20
+/// quirks = shift
+/// machine = eti660
+/// input = 10 press 5; 20 release 5
+/// expect_hash = 8a2bd1c0
+/// expect_text = 1A3
+/// expect_memory = 0x300 = 0x01
+/// ```
+///
+/// The `expect_*` keys can be repeated. Lines starting with `#` are comments.
+pub fn parse_manifest(manifest: &str) -> Result<Vec<TestCase>, String> {
+    let mut cases: Vec<TestCase> = Vec::new();
+
+    for (number, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        let error = |message: &str| format!("manifest line {}: {message}", number + 1);
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rom) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            cases.push(TestCase {
+                rom: PathBuf::from(rom.trim()),
+                frames: DEFAULT_FRAMES,
+                quirks: Quirks::default(),
+                machine: MachineConfig::default(),
+                input: InputScript::default(),
+                expect: Vec::new(),
+            });
+            continue;
+        }
+
+        let case = cases
+            .last_mut()
+            .ok_or_else(|| error("expected a [rom] section first"))?;
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| error("expected `key = value`"))?;
+
+        match key {
+            "frames" => case.frames = value.parse().map_err(|_| error("invalid frames"))?,
+            "quirks" => case.quirks = value.parse().map_err(|err: String| error(&err))?,
+            "machine" => case.machine = value.parse().map_err(|err: String| error(&err))?,
+            "input" => case.input = value.parse().map_err(|err: String| error(&err))?,
+            "expect_hash" => {
+                let hash = u32::from_str_radix(value.trim_start_matches("0x"), 16)
+                    .map_err(|_| error("invalid hash"))?;
+                case.expect.push(Expectation::Hash(hash));
+            }
+            "expect_text" => case.expect.push(Expectation::Text(value.to_string())),
+            "expect_memory" => {
+                let (address, byte) = value
+                    .split_once('=')
+                    .ok_or_else(|| error("expected `address = value`"))?;
+                let address = parse_int(address).ok_or_else(|| error("invalid address"))?;
+                let value = parse_int(byte)
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or_else(|| error("invalid value"))?;
+
+                let address =
+                    u16::try_from(address).map_err(|_| error("address is outside of memory"))?;
+                case.expect.push(Expectation::Memory { address, value });
+            }
+            _ => return Err(error(&format!("unknown key `{key}`"))),
+        }
+    }
+
+    Ok(cases)
+}
+
+/// Parses a decimal or `0x` prefixed hex number
+fn parse_int(value: &str) -> Option<u32> {
+    let value = value.trim();
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Runs one test. A ROM that faults fails.
+pub fn run_case(dir: &Path, case: &TestCase) -> TestResult {
+    let start = Instant::now();
+    let mut result = TestResult {
+        rom: case.rom.clone(),
+        duration: Duration::ZERO,
+        frames: 0,
+        status: None,
+        hash: None,
+        text: None,
+        failures: Vec::new(),
+    };
+
+    let path = dir.join(&case.rom);
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            result
+                .failures
+                .push(format!("could not read {}: {err}", path.display()));
+            return result;
+        }
+    };
+
+    let mut cpu = match CPU::with_machine(case.machine, &rom) {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            result.failures.push(err);
+            return result;
+        }
+    };
+    cpu.quirks = case.quirks;
+    result.frames = cpu.run_until_halt(case.frames, &case.input);
+    result.duration = start.elapsed();
+    result.status = Some(cpu.status);
+    if let RunStatus::Faulted { .. } = cpu.status {
+        result.failures.push(format!("emulator {}", cpu.status));
+    }
+
+    let hash = cpu.framebuffer_hash();
+    let text = cpu.screen_text();
+
+    for expectation in &case.expect {
+        match expectation {
+            Expectation::Hash(expected) if *expected != hash => result
+                .failures
+                .push(format!("expected hash {expected:08x}, got {hash:08x}")),
+            Expectation::Text(expected) if !text.contains(expected.as_str()) => result
+                .failures
+                .push(format!("expected text {expected:?}, got {text:?}")),
+            Expectation::Memory { address, .. } if *address as usize >= cpu.bus.size() => result
+                .failures
+                .push(format!("{address:#05x} is outside of memory")),
+            Expectation::Memory { address, value } if cpu.bus.peek(*address) != *value => {
+                result.failures.push(format!(
+                    "expected {value:#04x} at {address:#05x}, got {:#04x}",
+                    cpu.bus.peek(*address)
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    result.hash = Some(hash);
+    result.text = Some(text);
+    result
+}
+
+/// Runs every test on `jobs` threads, returning the results in manifest order
+pub fn run_suite(dir: &Path, cases: &[TestCase], jobs: usize) -> Vec<TestResult> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<TestResult>>> = Mutex::new(vec![None; cases.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, cases.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(case) = cases.get(index) else { break };
+
+                let result = run_case(dir, case);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every test has run"))
+        .collect()
+}
+
+/// Writes the results as a JSON document
+pub fn write_json<W: Write>(out: &mut W, results: &[TestResult]) -> io::Result<()> {
+    let failed = results.iter().filter(|result| !result.passed()).count();
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"passed\": {},", results.len() - failed)?;
+    writeln!(out, "  \"failed\": {failed},")?;
+    writeln!(out, "  \"tests\": [")?;
+
+    for (i, result) in results.iter().enumerate() {
+        let failures: Vec<String> = result.failures.iter().map(|f| json_string(f)).collect();
+
+        writeln!(out, "    {{")?;
+        writeln!(
+            out,
+            "      \"rom\": {},",
+            json_string(&result.rom.to_string_lossy())
+        )?;
+        writeln!(out, "      \"passed\": {},", result.passed())?;
+        writeln!(
+            out,
+            "      \"duration_ms\": {},",
+            result.duration.as_millis()
+        )?;
+        writeln!(out, "      \"frames\": {},", result.frames)?;
+        match result.status {
+            Some(status) => writeln!(
+                out,
+                "      \"status\": {},",
+                json_string(&status.to_string())
+            )?,
+            None => writeln!(out, "      \"status\": null,")?,
+        }
+        match result.hash {
+            Some(hash) => writeln!(out, "      \"hash\": \"{hash:08x}\",")?,
+            None => writeln!(out, "      \"hash\": null,")?,
+        }
+        match &result.text {
+            Some(text) => writeln!(out, "      \"text\": {},", json_string(text))?,
+            None => writeln!(out, "      \"text\": null,")?,
+        }
+        writeln!(out, "      \"failures\": [{}]", failures.join(", "))?;
+
+        let separator = if i + 1 < results.len() { "," } else { "" };
+        writeln!(out, "    }}{separator}")?;
+    }
+
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+/// Writes the results as a JUnit XML report
+pub fn write_junit<W: Write>(out: &mut W, results: &[TestResult]) -> io::Result<()> {
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    let total: Duration = results.iter().map(|result| result.duration).sum();
+
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        out,
+        "<testsuite name=\"chip8\" tests=\"{}\" failures=\"{failed}\" time=\"{:.3}\">",
+        results.len(),
+        total.as_secs_f64()
+    )?;
+
+    for result in results {
+        let name = xml_escape(&result.rom.to_string_lossy());
+        let time = result.duration.as_secs_f64();
+
+        if result.passed() {
+            writeln!(
+                out,
+                "  <testcase name=\"{name}\" classname=\"chip8\" time=\"{time:.3}\"/>"
+            )?;
+            continue;
+        }
+
+        writeln!(
+            out,
+            "  <testcase name=\"{name}\" classname=\"chip8\" time=\"{time:.3}\">"
+        )?;
+        let message = xml_escape(&result.failures.join("\n"));
+        writeln!(
+            out,
+            "    <failure message=\"{}\">{message}</failure>",
+            xml_escape(&result.failures[0])
+        )?;
+        writeln!(out, "  </testcase>")?;
+    }
+
+    writeln!(out, "</testsuite>")
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = "
+            # The IBM logo
+            [ibmlogo.ch8]
+            frames = 20
+            quirks = shift
+            input = 2 press A
+            expect_hash = 0xdeadbeef
+            expect_memory = 0x200 = 0x00
+
+            [other.ch8]
+            expect_text = 42
+        ";
+        let cases = parse_manifest(manifest).unwrap();
+
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].rom, PathBuf::from("ibmlogo.ch8"));
+        assert_eq!(cases[0].frames, 20);
+        assert!(cases[0].quirks.shift);
+        assert_eq!(cases[0].input.events.len(), 1);
+        assert_eq!(
+            cases[0].expect,
+            vec![
+                Expectation::Hash(0xDEAD_BEEF),
+                Expectation::Memory {
+                    address: 0x200,
+                    value: 0
+                }
+            ]
+        );
+        assert_eq!(cases[1].frames, DEFAULT_FRAMES);
+        assert_eq!(cases[1].expect, vec![Expectation::Text("42".to_string())]);
+
+        assert!(parse_manifest("frames = 10").is_err());
+        assert!(parse_manifest("[a.ch8]\ncolour = red").is_err());
+    }
+
+    #[test]
+    fn test_run_suite() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let manifest = "
+            [ibmlogo.ch8]
+            frames = 20
+            expect_memory = 0x200 = 0x00
+
+            [ibmlogo.ch8]
+            expect_memory = 0x200 = 0x01
+
+            [missing.ch8]
+        ";
+        let cases = parse_manifest(manifest).unwrap();
+        let results = run_suite(dir, &cases, 2);
+
+        assert!(results[0].passed());
+        assert!(results[0].hash.is_some());
+        assert!(!results[1].passed());
+        assert!(!results[2].passed());
+
+        let mut json = Vec::new();
+        write_json(&mut json, &results).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"passed\": 1,"));
+        assert!(json.contains("\"failed\": 2,"));
+
+        let mut junit = Vec::new();
+        write_junit(&mut junit, &results).unwrap();
+        let junit = String::from_utf8(junit).unwrap();
+        assert!(junit.contains("tests=\"3\" failures=\"2\""));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\n\"");
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use super::cpu::CPU;
+use super::halt::RunStatus;
+
+/// Stands in for a machine code subroutine a ROM calls with `0nnn`
+pub type SysHandler = fn(&mut CPU);
+
+/// Which interpreter's machine code subroutines `0nnn` calls stand in for, beyond the
+/// [`builtin_sys_handlers`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SysRoutines {
+    /// Only the routines every VIP interpreter has
+    #[default]
+    Vip,
+
+    /// The two page hi-res interpreter, which keeps a clear screen routine at 0x230
+    TwoPageHires,
+}
+
+impl SysRoutines {
+    /// The handlers these routines add
+    pub fn handlers(self) -> HashMap<u16, SysHandler> {
+        let mut handlers: HashMap<u16, SysHandler> = HashMap::new();
+
+        match self {
+            SysRoutines::Vip => {}
+            // Hi-res ROMs call 0230 to clear the screen. The standard interpreter has no
+            // routine there, so this is only installed by the `hires` machine.
+            SysRoutines::TwoPageHires => {
+                handlers.insert(0x230, CPU::clear);
+            }
+        }
+
+        handlers
+    }
+}
+
+/// The handlers every CPU starts with, for routines classic VIP programs call
+pub fn builtin_sys_handlers() -> HashMap<u16, SysHandler> {
+    let mut handlers: HashMap<u16, SysHandler> = HashMap::new();
+
+    // `0000` jumps back into the VIP monitor, which is how some programs end
+    handlers.insert(0x000, |cpu| {
+        cpu.rewind_pc();
+        cpu.status = RunStatus::Halted { pc: cpu.pc };
+    });
+
+    handlers
+}
+
+impl CPU {
+    /// Runs `handler` in place of the machine code subroutine at `address`, replacing any
+    /// handler already registered there
+    pub fn register_sys(&mut self, address: u16, handler: SysHandler) {
+        self.sys_handlers.insert(address & 0xFFF, handler);
+    }
+
+    /// 0nnn: calls the handler registered for `address`, or applies the unknown opcode
+    /// policy if there isn't one
+    pub fn sys0nnn(&mut self, address: u16) {
+        match self.sys_handlers.get(&address).copied() {
+            Some(handler) => handler(self),
+            None => self.unknown_opcode(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halt::Fault;
+    use crate::machine::MachineConfig;
+
+    #[test]
+    fn test_registered_handlers_run() {
+        // 0x200: 0456, 0x202: 0123
+        let mut cpu = CPU::new_with_memory(&[0x04, 0x56, 0x01, 0x23]);
+        cpu.register_sys(0x456, |cpu| cpu.registers[0] = 0x42);
+
+        cpu.step();
+        assert_eq!(cpu.registers[0], 0x42);
+        assert_eq!(cpu.status, RunStatus::Running);
+
+        cpu.step();
+        assert_eq!(
+            cpu.status,
+            RunStatus::Faulted {
+                pc: 0x202,
+                fault: Fault::UnknownOpcode { opcode: 0x0123 }
+            }
+        );
+    }
+
+    #[test]
+    fn test_builtin_handlers() {
+        // 0x200: 0000
+        let mut cpu = CPU::new_with_memory(&[0x00, 0x00]);
+
+        cpu.step();
+        assert_eq!(cpu.status, RunStatus::Halted { pc: 0x200 });
+    }
+
+    #[test]
+    fn test_hires_clear_is_opt_in() {
+        // 0x200: 0230
+        let rom = [0x02, 0x30];
+
+        let mut cpu = CPU::with_machine(MachineConfig::chip8(), &rom).unwrap();
+        cpu.step();
+        assert_eq!(
+            cpu.status,
+            RunStatus::Faulted {
+                pc: 0x200,
+                fault: Fault::UnknownOpcode { opcode: 0x0230 }
+            }
+        );
+
+        let mut cpu = CPU::with_machine(MachineConfig::hires(), &rom).unwrap();
+        cpu.buf.set(0, 0, true);
+        cpu.step();
+        assert!(!cpu.buf.get(0, 0));
+        assert_eq!(cpu.status, RunStatus::Running);
+    }
+}
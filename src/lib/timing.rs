@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use super::cpu::{CPU, INSTRUCTIONS_PER_FRAME};
+
+/// Machine cycles the VIP's 1802 runs per 60 Hz frame, 1.76 MHz at 8 clocks per cycle
+pub const VIP_CYCLES_PER_FRAME: i64 = 3668;
+
+/// Machine cycles per frame taken by the display interrupt routine and the CDP1861's DMA,
+/// which steals the bus for all 128 displayed lines
+pub const VIP_INTERRUPT_CYCLES: i64 = 1832;
+
+/// Machine cycles the interpreter spends fetching and dispatching every instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How the instructions of a frame are paced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// A fixed number of instructions per frame, however long they would really take
+    Instructions(usize),
+
+    /// Each instruction costs what the COSMAC VIP interpreter spends on it, and a frame
+    /// runs as many as fit in the cycles the display interrupt leaves over
+    Vip,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Instructions(INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    /// Parses `vip` or a number of instructions per frame
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "vip" => Ok(Timing::Vip),
+            count => match count.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "timing must be `vip` or a number of instructions per frame, got `{value}`"
+                )),
+                Ok(count) => Ok(Timing::Instructions(count)),
+            },
+        }
+    }
+}
+
+impl CPU {
+    /// Runs the instructions of one frame under the current [`Timing`], calling `step` to
+    /// execute each one. Stops early when `step` returns something, or when the CPU stalls
+    /// until vblank.
+    pub fn run_frame_instructions<T>(
+        &mut self,
+        mut step: impl FnMut(&mut CPU) -> Option<T>,
+    ) -> Option<T> {
+        match self.timing {
+            Timing::Instructions(count) => {
+                for _ in 0..count {
+                    if self.waiting_for_vblank {
+                        break;
+                    }
+                    if let Some(stop) = step(self) {
+                        return Some(stop);
+                    }
+                }
+            }
+            Timing::Vip => {
+                // An instruction that runs over the end of a frame eats into the next one
+                self.cycle_budget += VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+
+                while self.cycle_budget > 0 {
+                    if self.waiting_for_vblank {
+                        self.cycle_budget = 0;
+                        break;
+                    }
+
+                    self.cycle_budget -= self.vip_cycles() as i64;
+                    if let Some(stop) = step(self) {
+                        return Some(stop);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The machine cycles the VIP interpreter spends on the instruction at `pc`, given
+    /// the current registers
+    pub fn vip_cycles(&self) -> u32 {
+        let Some(opcode) = self.opcode_at(self.pc) else {
+            return VIP_FETCH_CYCLES;
+        };
+
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as u32;
+        let nn = (opcode & 0xFF) as u8;
+        let (vx, vy) = (self.registers[x], self.registers[y]);
+
+        // Skips cost a little extra when they are taken
+        let skip = |taken: bool| if taken { 4 } else { 0 };
+
+        let cost = match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => 24 + 3054,
+            0x0 if opcode == 0x00EE => 10,
+            // Machine code subroutines run for as long as they run, charge the call itself
+            0x0 => 12,
+            0x1 => 12,
+            0x2 => 26,
+            0x3 => 10 + skip(vx == nn),
+            0x4 => 10 + skip(vx != nn),
+            0x5 => 14 + skip(vx == vy),
+            0x6 => 6,
+            0x7 => 10,
+            0x8 => 44,
+            0x9 => 14 + skip(vx != vy),
+            0xA => 12,
+            // Crossing into another 256 byte page costs an extra cycle pair
+            0xB => {
+                22 + if (opcode & 0xFF) + self.registers[0] as u16 > 0xFF {
+                    2
+                } else {
+                    0
+                }
+            }
+            0xC => 36,
+            0xD => self.vip_draw_cycles(vx, n),
+            0xE if nn == 0x9E => 14 + skip(self.keys[(vx & 0xF) as usize]),
+            0xE => 14 + skip(!self.keys[(vx & 0xF) as usize]),
+            0xF => match nn {
+                0x1E => {
+                    16 + if self.i_reg as u32 + vx as u32 > 0xFF {
+                        4
+                    } else {
+                        0
+                    }
+                }
+                0x29 => 16,
+                // One pass of the subtraction loop per unit in each digit
+                0x33 => 80 + (vx / 100 + vx / 10 % 10 + vx % 10) as u32 * 16,
+                0x55 | 0x65 => 14 + (x as u32 + 1) * 14,
+                _ => 10,
+            },
+            _ => 0,
+        };
+
+        VIP_FETCH_CYCLES + cost
+    }
+
+    /// Dxyn draws byte by byte, and a sprite that isn't byte aligned is shifted into two
+    /// bytes one bit at a time, so the cost grows with both the height and x mod 8
+    fn vip_draw_cycles(&self, vx: u8, rows: u32) -> u32 {
+        let shift = (vx % 8) as u32;
+        let second_byte = if shift == 0 { 0 } else { 20 };
+
+        68 + rows * (46 + shift * 8 + second_byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vip_cycles() {
+        // 0x200: 6005, 0x202: 3005 (skips), 0x204: D125
+        let mut cpu = CPU::new_with_memory(&[0x60, 0x05, 0x30, 0x05, 0xD1, 0x25]);
+        assert_eq!(cpu.vip_cycles(), 46);
+
+        cpu.step();
+        assert_eq!(cpu.vip_cycles(), 54);
+
+        cpu.pc = 0x204;
+        let aligned = cpu.vip_cycles();
+        cpu.registers[1] = 3;
+        assert!(cpu.vip_cycles() > aligned);
+    }
+
+    #[test]
+    fn test_vip_timing_runs_as_many_instructions_as_fit() {
+        // 0x200: 7001 (50 cycles), 0x202: 7101 (50 cycles), 0x204: 1200 (52 cycles)
+        let mut cpu = CPU::new_with_memory(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        cpu.timing = Timing::Vip;
+
+        cpu.run_frame();
+
+        // 1836 cycles fit 12 trips around the loop, and the next add runs over the frame
+        assert_eq!(cpu.registers[0], 13);
+        assert_eq!(cpu.registers[1], 12);
+        assert_eq!(cpu.cycle_budget, -38);
+
+        // Which the next frame pays for
+        cpu.run_frame();
+        assert_eq!(cpu.registers[0], 25);
+        assert_eq!(cpu.cycle_budget, -26);
+    }
+
+    #[test]
+    fn test_parse_timing() {
+        assert_eq!("vip".parse(), Ok(Timing::Vip));
+        assert_eq!("20".parse(), Ok(Timing::Instructions(20)));
+        assert!("0".parse::<Timing>().is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+use super::cpu::CPU;
+use super::halt::Fault;
+
+/// What happens when the CPU meets an opcode nothing handles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    /// Stop with [`Fault::UnknownOpcode`], so the run ends with a crash report
+    #[default]
+    Fault,
+
+    /// Carry on with the next instruction as if it were a no-op
+    Ignore,
+}
+
+impl FromStr for UnknownOpcodePolicy {
+    type Err = String;
+
+    /// Parses `fault` or `ignore`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "fault" => Ok(UnknownOpcodePolicy::Fault),
+            "ignore" => Ok(UnknownOpcodePolicy::Ignore),
+            _ => Err(format!("unknown opcode policy `{value}`")),
+        }
+    }
+}
+
+impl CPU {
+    /// Runs the extension registered for an opcode the CPU doesn't implement, or applies
+    /// the unknown opcode policy if there isn't one
+    pub fn unknown_opcode(&mut self, opcode: u16) {
+        if self.run_extension(opcode) {
+            return;
+        }
+
+        match self.unknown_opcodes {
+            UnknownOpcodePolicy::Fault => self.fault(Fault::UnknownOpcode { opcode }),
+            UnknownOpcodePolicy::Ignore => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halt::RunStatus;
+
+    #[test]
+    fn test_unknown_opcodes_fault_or_are_ignored() {
+        // 0x200: 5121, 0x202: 6105
+        let mut cpu = CPU::new_with_memory(&[0x51, 0x21, 0x61, 0x05]);
+        cpu.step();
+        assert_eq!(
+            cpu.status,
+            RunStatus::Faulted {
+                pc: 0x200,
+                fault: Fault::UnknownOpcode { opcode: 0x5121 }
+            }
+        );
+
+        let mut cpu = CPU::new_with_memory(&[0x51, 0x21, 0x61, 0x05]);
+        cpu.unknown_opcodes = "ignore".parse().unwrap();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers[1], 5);
+    }
+}
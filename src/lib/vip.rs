@@ -0,0 +1,318 @@
+use super::cdp1802::{Board, Cdp1802};
+use super::display::print_screen;
+use super::framebuffer::Framebuffer;
+use super::limits::OutputBudget;
+use super::sink::Sinks;
+
+/// How much RAM a fully expanded VIP has
+pub const VIP_RAM_SIZE: usize = 4096;
+
+/// The most bytes of interpreter that fit below the program at 0x200
+pub const INTERPRETER_SIZE: usize = 0x200;
+
+/// Where the interpreter expects the CHIP-8 program
+const PROGRAM_START: usize = 0x200;
+
+/// The CDP1861 draws 262 lines of 14 machine cycles each per frame
+const LINE_CYCLES: u32 = 14;
+const FRAME_CYCLES: u32 = 262 * LINE_CYCLES;
+
+/// The 128 lines the CDP1861 fetches pixels for, each one a burst of 8 DMA cycles at the
+/// start of the line
+const FIRST_DISPLAY_LINE: u32 = 80;
+const DISPLAY_LINES: u32 = 128;
+const DMA_BYTES: usize = 8;
+
+/// The CDP1861 raises its interrupt 29 machine cycles before the first DMA, plus the one
+/// the 1802 takes to answer it, which is exactly what the interpreter's interrupt routine
+/// spends before DMA starts
+const INTERRUPT_CYCLE: u32 = FIRST_DISPLAY_LINE * LINE_CYCLES - 30;
+
+/// The lines EF1 is asserted on, four lines before the display starts and ends
+const EF1_LINES: [std::ops::Range<u32>; 2] = [
+    FIRST_DISPLAY_LINE - 4..FIRST_DISPLAY_LINE,
+    FIRST_DISPLAY_LINE + DISPLAY_LINES - 4..FIRST_DISPLAY_LINE + DISPLAY_LINES,
+];
+
+/// Everything on the VIP's board the 1802 talks to: RAM, the CDP1861 display chip and the
+/// hex keypad
+pub struct VipBoard {
+    pub ram: Vec<u8>,
+
+    /// The keys held down on the hex keypad
+    pub keys: [bool; 16],
+
+    /// The key `OUT 2` selected, EF3 tells whether it's down
+    pub key_latch: u8,
+
+    /// Whether the CDP1861 is on, `INP 1` turns it on and `OUT 1` off
+    pub display_on: bool,
+
+    /// The machine cycle within the current frame
+    pub cycle: u32,
+}
+
+impl Board for VipBoard {
+    /// RAM repeats through the lower 32 KiB. The monitor ROM at 0x8000 isn't emulated,
+    /// the interpreter is started without it.
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000.. => 0,
+            _ => self.ram[address as usize % self.ram.len()],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            let len = self.ram.len();
+            self.ram[address as usize % len] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = value & 0xF,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+
+        // Nothing drives the bus, it reads as all ones
+        0xFF
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        match flag {
+            1 => {
+                let line = self.cycle / LINE_CYCLES;
+                self.display_on && EF1_LINES.iter().any(|lines| lines.contains(&line))
+            }
+            3 => self.keys[self.key_latch as usize],
+            _ => false,
+        }
+    }
+}
+
+/// A COSMAC VIP running the original CHIP-8 interpreter on an emulated 1802, an
+/// alternative to the high level [`CPU`] to check its behaviour against. Timers, sound,
+/// the keypad and 0nnn machine code subroutines are all left to the interpreter.
+///
+/// [`CPU`]: super::cpu::CPU
+pub struct Vip {
+    pub cpu: Cdp1802,
+    pub board: VipBoard,
+
+    /// The screen as the CDP1861 last drew it, each of the interpreter's 32 rows is
+    /// repeated over four lines
+    pub buf: Framebuffer,
+
+    /// Receivers of every finished frame
+    pub sinks: Sinks,
+
+    /// Counts the bytes written by outputs that are wrapped with it
+    pub output: OutputBudget,
+}
+
+impl Vip {
+    /// A 4 KiB VIP with `interpreter` loaded at 0x000 and `program` at 0x200, started the
+    /// way the monitor hands over to the interpreter: at 0x000 with R1.1 holding the top
+    /// page of RAM
+    pub fn new(interpreter: &[u8], program: &[u8]) -> Result<Self, String> {
+        if interpreter.len() > INTERPRETER_SIZE {
+            return Err(format!(
+                "the interpreter is {} bytes, at most {INTERPRETER_SIZE} fit below 0x200",
+                interpreter.len()
+            ));
+        }
+        if program.len() > VIP_RAM_SIZE - PROGRAM_START {
+            return Err(format!(
+                "the ROM is {} bytes, at most {} fit in VIP memory",
+                program.len(),
+                VIP_RAM_SIZE - PROGRAM_START
+            ));
+        }
+
+        let mut ram = vec![0; VIP_RAM_SIZE];
+        ram[..interpreter.len()].copy_from_slice(interpreter);
+        ram[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+
+        let mut cpu = Cdp1802::new();
+        cpu.r[1] = ((VIP_RAM_SIZE / 256 - 1) as u16) << 8;
+
+        Ok(Vip {
+            cpu,
+            board: VipBoard {
+                ram,
+                keys: [false; 16],
+                key_latch: 0,
+                display_on: false,
+                cycle: 0,
+            },
+            buf: Framebuffer::new(),
+            sinks: Sinks::default(),
+            output: OutputBudget::default(),
+        })
+    }
+
+    /// Runs the 1802 for one 60 Hz frame, with the display interrupt and DMA where the
+    /// CDP1861 puts them, then hands the frame to the sinks
+    pub fn run_frame(&mut self) {
+        let mut interrupted = false;
+        let mut line = FIRST_DISPLAY_LINE;
+
+        while self.board.cycle < FRAME_CYCLES {
+            let cycle = self.board.cycle;
+
+            // An interrupt still masked when the display starts is missed for this frame
+            if self.board.display_on
+                && !interrupted
+                && (INTERRUPT_CYCLE..FIRST_DISPLAY_LINE * LINE_CYCLES).contains(&cycle)
+                && self.cpu.ie
+            {
+                self.board.cycle += self.cpu.interrupt();
+                interrupted = true;
+                continue;
+            }
+
+            if self.board.display_on
+                && line < FIRST_DISPLAY_LINE + DISPLAY_LINES
+                && cycle >= line * LINE_CYCLES
+            {
+                self.dma_line(line - FIRST_DISPLAY_LINE);
+                self.board.cycle += DMA_BYTES as u32;
+                line += 1;
+                continue;
+            }
+
+            self.board.cycle += self.cpu.step(&mut self.board);
+        }
+        self.board.cycle -= FRAME_CYCLES;
+
+        if !self.board.display_on {
+            self.buf.clear();
+        }
+        self.sinks.present(&self.buf, self.sound());
+    }
+
+    /// Fetches one line of pixels, keeping the first of every four
+    fn dma_line(&mut self, line: u32) {
+        let mut bytes = [0; DMA_BYTES];
+        for byte in bytes.iter_mut() {
+            *byte = self.cpu.dma_out(&mut self.board);
+        }
+
+        if line.is_multiple_of(4) {
+            self.buf
+                .set_row((line / 4) as usize, u64::from_be_bytes(bytes));
+        }
+    }
+
+    /// Whether the VIP's tone is sounding, which the interpreter drives through Q
+    pub fn sound(&self) -> bool {
+        self.cpu.q
+    }
+
+    /// Prints the screen to the terminal
+    pub fn update(&self) {
+        print_screen(&self.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::input::InputScript;
+    use crate::sink::FrameSink;
+
+    /// Points the interrupt at a routine showing the page at 0x100 without repeating
+    /// lines, moves the program counter off R0, which DMA uses, turns the display and
+    /// the tone on and spins
+    const INTERPRETER: [u8; 0x28] = [
+        0xF8, 0x00, 0xB1, 0xB3, // R1 = 0x001C, the interrupt routine
+        0xF8, 0x1C, 0xA1, //
+        0xF8, 0x12, 0xA3, // R3 = 0x0012
+        0xF8, 0x0E, 0xB2, // R2 = 0x0EFF, the stack
+        0xF8, 0xFF, 0xA2, //
+        0xE2, 0xD3, // SEX 2, SEP 3
+        0x69, 0x7B, // 0x12: INP 1, SEQ
+        0x30, 0x14, // BR 0x14
+        0x00, 0x00, 0x00, 0x00, //
+        0x42, 0x70, // 0x1A: restore D and return
+        0x22, 0x78, 0x22, 0x52, // 0x1C: save T and D
+        0xF8, 0x01, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0100
+        0x30, 0x1A, // BR 0x1A
+    ];
+
+    #[test]
+    fn test_interrupt_and_dma_draw_the_screen() {
+        let mut vip = Vip::new(&INTERPRETER, &[]).unwrap();
+        // The first line of row 5
+        vip.board.ram[0x100 + 5 * 4 * DMA_BYTES] = 0xF0;
+
+        vip.run_frame();
+
+        assert!(vip.board.display_on);
+        assert!(vip.sound());
+        assert!(vip.buf.get(0, 5) && vip.buf.get(3, 5));
+        assert!(!vip.buf.get(4, 5) && !vip.buf.get(0, 4));
+        assert_eq!(
+            vip.cpu.r[0],
+            0x100 + (DISPLAY_LINES as usize * DMA_BYTES) as u16
+        );
+        // Back in the spin loop with interrupts enabled again
+        assert_eq!((vip.cpu.p, vip.cpu.x), (3, 2));
+        assert!(vip.cpu.ie);
+    }
+
+    #[test]
+    fn test_keypad_latch() {
+        let mut vip = Vip::new(&[], &[]).unwrap();
+        vip.board.keys[0xA] = true;
+
+        vip.board.output(2, 0x1A);
+        assert!(vip.board.flag(3));
+
+        vip.board.output(2, 0x05);
+        assert!(!vip.board.flag(3));
+    }
+
+    /// Keeps how many frames it saw with the sound on
+    struct SoundFrames(Rc<Cell<usize>>);
+
+    impl FrameSink for SoundFrames {
+        fn present(&mut self, _frame: &Framebuffer, sound: bool) -> io::Result<()> {
+            self.0.set(self.0.get() + usize::from(sound));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_scripted_runs_reach_the_sinks() {
+        let mut vip = Vip::new(&INTERPRETER, &[]).unwrap();
+        let frames = Rc::new(Cell::new(0));
+        vip.sinks.push(Box::new(SoundFrames(frames.clone())));
+
+        let input: InputScript = "2 press A".parse().unwrap();
+        vip.run_scripted(3, &input);
+
+        assert_eq!(frames.get(), 3);
+        assert!(vip.board.keys[0xA]);
+        assert!(vip.finish_sinks().is_ok());
+    }
+
+    #[test]
+    fn test_oversized_images_are_rejected() {
+        assert!(Vip::new(&[0; INTERPRETER_SIZE + 1], &[]).is_err());
+        assert!(Vip::new(&[], &[0; VIP_RAM_SIZE]).is_err());
+    }
+}
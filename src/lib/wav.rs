@@ -0,0 +1,115 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use super::framebuffer::Framebuffer;
+use super::sink::FrameSink;
+
+/// The sample rate of recordings, a whole number of samples per 60 Hz frame
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Samples written for every frame
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / 60;
+
+/// The pitch of the beeper
+const TONE_HZ: u32 = 440;
+
+/// The amplitude of the square wave
+const VOLUME: i16 = 0x2000;
+
+/// Records the sound timer as a mono 16-bit PCM WAV, one frame of samples per 60 Hz frame
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes a header whose sizes are filled in by [`WavWriter::finalize`]
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&header(0))?;
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    /// Writes one frame of a square wave if the beeper is on, silence otherwise
+    pub fn write_frame(&mut self, sound: bool) -> io::Result<()> {
+        let half_period = SAMPLE_RATE / (TONE_HZ * 2);
+
+        let mut samples = Vec::with_capacity(SAMPLES_PER_FRAME as usize * 2);
+        for _ in 0..SAMPLES_PER_FRAME {
+            // The phase carries over between frames so the tone does not click
+            let sample = match (sound, (self.samples / half_period) % 2) {
+                (false, _) => 0,
+                (true, 0) => VOLUME,
+                (true, _) => -VOLUME,
+            };
+            samples.extend_from_slice(&sample.to_le_bytes());
+            self.samples += 1;
+        }
+
+        self.out.write_all(&samples)
+    }
+
+    /// Fills in the sizes in the header now that the length is known
+    pub fn finalize(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&header(self.samples * 2))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Seek> FrameSink for WavWriter<W> {
+    fn present(&mut self, _frame: &Framebuffer, sound: bool) -> io::Result<()> {
+        self.write_frame(sound)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finalize()
+    }
+}
+
+/// The RIFF header for `data_len` bytes of mono 16-bit samples
+fn header(data_len: u32) -> [u8; 44] {
+    let mut header = [0; 44];
+
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(36 + data_len).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    header[20..22].copy_from_slice(&1u16.to_le_bytes());
+    header[22..24].copy_from_slice(&1u16.to_le_bytes());
+    header[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    // Byte rate, block align and bits per sample
+    header[28..32].copy_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    header[32..34].copy_from_slice(&2u16.to_le_bytes());
+    header[34..36].copy_from_slice(&16u16.to_le_bytes());
+
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_sizes() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.write_frame(false).unwrap();
+        writer.write_frame(true).unwrap();
+        writer.finalize().unwrap();
+
+        let out = writer.out.into_inner();
+        let data_len = SAMPLES_PER_FRAME * 2 * 2;
+        assert_eq!(out.len(), 44 + data_len as usize);
+        assert_eq!(&out[40..44], &data_len.to_le_bytes());
+
+        // Silence first, then the tone
+        assert!(out[44..44 + 2 * 735].iter().all(|byte| *byte == 0));
+        assert_ne!(&out[44 + 2 * 735..44 + 2 * 736], &[0, 0]);
+    }
+}
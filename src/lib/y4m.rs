@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+
+use super::framebuffer::Framebuffer;
+use super::png::Rgb;
+use super::sink::FrameSink;
+
+/// Streams frames as an uncompressed YUV4MPEG2 video at 60 fps, e.g. for `ffmpeg -i out.y4m`
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: usize,
+    scale: usize,
+
+    /// The Y, U and V values of each palette entry
+    palette: [[u8; 3]; 4],
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header, every pixel becomes a `scale` x `scale` block
+    pub fn new(
+        mut out: W,
+        width: usize,
+        height: usize,
+        scale: u32,
+        palette: [Rgb; 4],
+    ) -> io::Result<Self> {
+        let scale = scale.max(1) as usize;
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444",
+            width * scale,
+            height * scale
+        )?;
+
+        Ok(Y4mWriter {
+            out,
+            width,
+            scale,
+            palette: palette.map(rgb_to_yuv),
+        })
+    }
+
+    /// Writes one frame of palette indices
+    pub fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        self.out.write_all(b"FRAME\n")?;
+
+        // C444 stores the full resolution Y plane, then U, then V
+        for plane in 0..3 {
+            for row in pixels.chunks(self.width) {
+                let scanline: Vec<u8> = row
+                    .iter()
+                    .flat_map(|pixel| {
+                        let value = self.palette[(*pixel).min(3) as usize][plane];
+                        std::iter::repeat_n(value, self.scale)
+                    })
+                    .collect();
+                for _ in 0..self.scale {
+                    self.out.write_all(&scanline)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn present(&mut self, frame: &Framebuffer, _sound: bool) -> io::Result<()> {
+        self.write_frame(&frame.pixels())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Converts a colour to limited range BT.601 YUV
+pub fn rgb_to_yuv([r, g, b]: Rgb) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+
+    [y as u8, u as u8, v as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::DEFAULT_PALETTE;
+
+    #[test]
+    fn test_rgb_to_yuv() {
+        assert_eq!(rgb_to_yuv([0, 0, 0]), [16, 128, 128]);
+        assert_eq!(rgb_to_yuv([255, 255, 255]), [235, 128, 128]);
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let mut writer = Y4mWriter::new(Vec::new(), 2, 1, 2, DEFAULT_PALETTE).unwrap();
+        writer.write_frame(&[0, 1]).unwrap();
+
+        let header = b"YUV4MPEG2 W4 H2 F60:1 Ip A1:1 C444\n";
+        let out = writer.out;
+        assert_eq!(&out[..header.len()], header);
+
+        let frame = &out[header.len()..];
+        assert_eq!(&frame[..6], b"FRAME\n");
+        assert_eq!(frame.len(), 6 + 3 * 4 * 2);
+        assert_eq!(&frame[6..10], &[16, 16, 235, 235]);
+    }
+}
@@ -1,28 +1,633 @@
-mod lib;
+use std::fs::File;
+use std::io::{BufRead, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use std::io::prelude::*;
+use chip8::cpu::{CPU, FRAME_DURATION};
+use chip8::display::{HEIGHT, WIDTH};
+use chip8::emulator::Emulator;
+use chip8::export::ImageFormat;
+use chip8::font::FontSet;
+use chip8::gif::GifOptions;
+use chip8::headless::{self, numbered_path, GifCapture, HeadlessOptions};
+use chip8::input::InputScript;
+use chip8::limits::{LimitTracker, Limits, RunReport, StopReason};
+use chip8::machine::MachineConfig;
+use chip8::png::{PngOptions, Rgb};
+use chip8::protect::Protection;
+use chip8::quirks::Quirks;
+use chip8::stack::StackConfig;
+use chip8::suite::{self, TestResult};
+use chip8::timing::Timing;
+use chip8::unknown::UnknownOpcodePolicy;
+use chip8::vip::Vip;
+use chip8::wav::WavWriter;
+use chip8::y4m::Y4mWriter;
+
+const USAGE: &str = "\
+Usage: chip8 run [OPTIONS] <ROM>
+       chip8 test [OPTIONS] <DIR>
+
+Options for run:
+    --headless          Run without printing to the terminal
+    --quirks <QUIRKS>   Comma separated quirks to enable: shift, wrap, vblank
+    --machine <MACHINE> chip8, vip2k, eti660, hires (0230 clears the screen) or xochip, optionally
+                        followed by memory=, load= and font= overrides, e.g. eti660,memory=2048
+                        [default: chip8]
+    --font <FONT>       chip8, schip, vip, dream6800, eti660, fishnchips or a file of 80 bytes
+                        of small font, optionally followed by 160 bytes of big font
+                        [default: the machine's font]
+    --stack <STACK>     vip (12 levels in memory), schip (16 levels), unlimited or a depth
+                        [default: schip]
+    --timing <TIMING>   vip (real COSMAC VIP instruction timing) or instructions per frame
+                        [default: 11]
+    --unknown-opcodes <POLICY>
+                        fault (stop with a crash report) or ignore [default: fault]
+    --interpreter <PATH>
+                        Run on an emulated COSMAC VIP booting this 512 byte CHIP-8 interpreter,
+                        which rules out the options that configure the CPU: --quirks,
+                        --machine, --font, --stack, --timing, --unknown-opcodes, --protect,
+                        --crash-report, --max-instructions and --stop-on-halt
+    --protect <MODE>    off, warn (report writes below the load address or to the font, reads
+                        past the ROM, I overflowing and accesses past the end of memory) or
+                        block (also drop those writes, and read 0 instead of past the end of
+                        memory or uninitialised memory past the ROM)
+                        [default: off]
+    --frames <N>        Number of frames to run in headless mode [default: 600]
+    --stop-on-halt      Stop a headless run early once the ROM halts or waits forever
+    --max-instructions <N>
+                        Stop a headless run after N instructions
+    --timeout <SECONDS> Stop a headless run after this much wall-clock time
+    --max-output <BYTES>
+                        Stop a headless run before its files grow past this many bytes in total
+    --input <SCRIPT>    Key presses to replay in headless mode, e.g. \"10 press 5; 20 release 5\"
+    --every <K>         Also write out every K-th frame in headless mode
+    --output <PATH>     Where to write frames, stdout if not given
+    --format <FORMAT>   pbm, pgm, txt or png, guessed from the output path if not given
+    --screenshot <PATH> Where PNG screenshots are saved [default: screenshot.png]
+    --screenshot-frame <F>
+                        Save a screenshot at frame F in headless mode, can be repeated
+    --gif <PATH>        Record an animated GIF in headless mode
+    --gif-frames <A..B> The first and last frame to record [default: all frames]
+    --y4m <PATH>        Stream every frame to a 60 fps YUV4MPEG2 video in headless mode
+    --wav <PATH>        Record the sound timer to a WAV file in headless mode
+    --crash-report <PATH>
+                        Where to write the crash report if the ROM crashes, stderr if not given
+    --scale <N>         Scale of PNG, GIF and Y4M output [default: 8]
+    --palette <COLORS>  Comma separated hex colours of PNG, GIF and Y4M output, e.g. 000000,ffffff
+
+While running interactively, type p and press Enter to save a screenshot.
+
+Options for test:
+    --manifest <PATH>   The test manifest [default: <DIR>/manifest.ini]
+    --report <FORMAT>   json or junit [default: json]
+    --output <PATH>     Where to write the report, stdout if not given
+    --jobs <N>          How many ROMs to run at once [default: number of CPUs]";
+
+/// Options that configure the high level CPU, which an emulated COSMAC VIP running its own
+/// interpreter has no use for
+const CPU_ONLY_FLAGS: [&str; 10] = [
+    "--quirks",
+    "--machine",
+    "--font",
+    "--stack",
+    "--timing",
+    "--unknown-opcodes",
+    "--protect",
+    "--crash-report",
+    "--max-instructions",
+    "--stop-on-halt",
+];
+
+/// The options of the `run` subcommand
+struct RunArgs {
+    rom: PathBuf,
+    headless: bool,
+    stop_on_halt: bool,
+    limits: Limits,
+    quirks: Quirks,
+    machine: MachineConfig,
+    font: Option<FontSet>,
+    stack: StackConfig,
+    timing: Timing,
+    unknown_opcodes: UnknownOpcodePolicy,
+    protection: Protection,
+    frames: usize,
+    input: InputScript,
+    every: Option<usize>,
+    output: Option<PathBuf>,
+    format: Option<ImageFormat>,
+    screenshot: PathBuf,
+    screenshot_frames: Vec<usize>,
+    png: PngOptions,
+    gif: Option<PathBuf>,
+    gif_frames: Option<(usize, usize)>,
+    y4m: Option<PathBuf>,
+    wav: Option<PathBuf>,
+    interpreter: Option<PathBuf>,
+    crash_report: Option<PathBuf>,
+
+    /// The [`CPU_ONLY_FLAGS`] given, in order
+    cpu_flags: Vec<String>,
+}
+
+/// The options of the `test` subcommand
+struct TestArgs {
+    dir: PathBuf,
+    manifest: Option<PathBuf>,
+    junit: bool,
+    output: Option<PathBuf>,
+    jobs: usize,
+}
 
 fn main() {
-    let mut bytes: Vec<u8> = Vec::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if let Err(err) = run_cli(&args) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
+
+fn run_cli(args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        Some("run") => run(parse_run_args(&args[1..])?),
+        Some("test") => test(parse_test_args(&args[1..])?),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command `{command}`\n\n{USAGE}")),
+        None => Err(format!("no command given\n\n{USAGE}")),
+    }
+}
+
+fn parse_run_args(args: &[String]) -> Result<RunArgs, String> {
+    let mut run_args = RunArgs {
+        rom: PathBuf::new(),
+        headless: false,
+        stop_on_halt: false,
+        limits: Limits::unlimited(),
+        quirks: Quirks::default(),
+        machine: MachineConfig::default(),
+        font: None,
+        stack: StackConfig::default(),
+        timing: Timing::default(),
+        unknown_opcodes: UnknownOpcodePolicy::default(),
+        protection: Protection::default(),
+        frames: 600,
+        input: InputScript::default(),
+        every: None,
+        output: None,
+        format: None,
+        screenshot: PathBuf::from("screenshot.png"),
+        screenshot_frames: Vec::new(),
+        png: PngOptions::default(),
+        gif: None,
+        gif_frames: None,
+        y4m: None,
+        wav: None,
+        interpreter: None,
+        crash_report: None,
+        cpu_flags: Vec::new(),
+    };
+    let mut rom = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if CPU_ONLY_FLAGS.contains(&arg.as_str()) {
+            run_args.cpu_flags.push(arg.clone());
+        }
+
+        match arg.as_str() {
+            "--headless" => run_args.headless = true,
+            "--stop-on-halt" => run_args.stop_on_halt = true,
+            "--max-instructions" => {
+                let max = parse_number(next_value(&mut args, arg)?, arg)?;
+                run_args.limits.max_instructions = Some(max as u64);
+            }
+            "--timeout" => {
+                let value = next_value(&mut args, arg)?;
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .ok_or_else(|| format!("{arg} expects a number of seconds, got `{value}`"))?;
+                run_args.limits.max_duration = Some(seconds);
+            }
+            "--max-output" => {
+                let max = parse_number(next_value(&mut args, arg)?, arg)?;
+                run_args.limits.max_output_bytes = Some(max as u64);
+            }
+            "--quirks" => run_args.quirks = next_value(&mut args, arg)?.parse()?,
+            "--machine" => run_args.machine = next_value(&mut args, arg)?.parse()?,
+            "--font" => run_args.font = Some(parse_font(next_value(&mut args, arg)?)?),
+            "--stack" => run_args.stack = next_value(&mut args, arg)?.parse()?,
+            "--timing" => run_args.timing = next_value(&mut args, arg)?.parse()?,
+            "--unknown-opcodes" => {
+                run_args.unknown_opcodes = next_value(&mut args, arg)?.parse()?;
+            }
+            "--protect" => run_args.protection = next_value(&mut args, arg)?.parse()?,
+            "--frames" => run_args.frames = parse_number(next_value(&mut args, arg)?, arg)?,
+            "--input" => run_args.input = next_value(&mut args, arg)?.parse()?,
+            "--every" => match parse_number(next_value(&mut args, arg)?, arg)? {
+                0 => return Err("--every must be at least 1".to_string()),
+                every => run_args.every = Some(every),
+            },
+            "--output" => run_args.output = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--format" => {
+                let value = next_value(&mut args, arg)?;
+                let format = ImageFormat::from_extension(value)
+                    .ok_or_else(|| format!("unknown format `{value}`"))?;
+                run_args.format = Some(format);
+            }
+            "--screenshot" => {
+                run_args.screenshot = PathBuf::from(next_value(&mut args, arg)?);
+            }
+            "--screenshot-frame" => {
+                let frame = parse_number(next_value(&mut args, arg)?, arg)?;
+                run_args.screenshot_frames.push(frame);
+            }
+            "--scale" => match parse_number(next_value(&mut args, arg)?, arg)? {
+                0 => return Err("--scale must be at least 1".to_string()),
+                scale => run_args.png.scale = scale as u32,
+            },
+            "--gif" => run_args.gif = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--gif-frames" => {
+                let value = next_value(&mut args, arg)?;
+                let (first, last) = value
+                    .split_once("..")
+                    .ok_or_else(|| format!("--gif-frames expects A..B, got `{value}`"))?;
+                run_args.gif_frames = Some((parse_number(first, arg)?, parse_number(last, arg)?));
+            }
+            "--y4m" => run_args.y4m = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--wav" => run_args.wav = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--interpreter" => {
+                run_args.interpreter = Some(PathBuf::from(next_value(&mut args, arg)?));
+            }
+            "--crash-report" => {
+                run_args.crash_report = Some(PathBuf::from(next_value(&mut args, arg)?));
+            }
+            "--palette" => parse_palette(next_value(&mut args, arg)?, &mut run_args.png)?,
+            flag if flag.starts_with("--") => return Err(format!("unknown option `{flag}`")),
+            path if rom.is_none() => rom = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument `{extra}`")),
+        }
+    }
+
+    run_args.rom = rom.ok_or_else(|| format!("no ROM given\n\n{USAGE}"))?;
+    Ok(run_args)
+}
+
+fn parse_test_args(args: &[String]) -> Result<TestArgs, String> {
+    let mut test_args = TestArgs {
+        dir: PathBuf::new(),
+        manifest: None,
+        junit: false,
+        output: None,
+        jobs: std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+    };
+    let mut dir = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--manifest" => test_args.manifest = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--report" => match next_value(&mut args, arg)?.as_str() {
+                "json" => test_args.junit = false,
+                "junit" => test_args.junit = true,
+                other => return Err(format!("unknown report format `{other}`")),
+            },
+            "--output" => test_args.output = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "--jobs" => match parse_number(next_value(&mut args, arg)?, arg)? {
+                0 => return Err("--jobs must be at least 1".to_string()),
+                jobs => test_args.jobs = jobs,
+            },
+            flag if flag.starts_with("--") => return Err(format!("unknown option `{flag}`")),
+            path if dir.is_none() => dir = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument `{extra}`")),
+        }
+    }
+
+    test_args.dir = dir.ok_or_else(|| format!("no directory given\n\n{USAGE}"))?;
+    Ok(test_args)
+}
+
+fn next_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, String> {
+    args.next().ok_or_else(|| format!("{flag} expects a value"))
+}
+
+fn parse_number(value: &str, flag: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got `{value}`"))
+}
+
+/// Parses two or four comma separated hex colours into the PNG palette
+fn parse_palette(value: &str, png: &mut PngOptions) -> Result<(), String> {
+    let colors = value
+        .split(',')
+        .map(parse_color)
+        .collect::<Option<Vec<Rgb>>>()
+        .ok_or_else(|| format!("invalid palette `{value}`"))?;
+
+    if colors.len() != 2 && colors.len() != 4 {
+        return Err("--palette expects two or four colours".to_string());
+    }
+
+    png.palette[..colors.len()].copy_from_slice(&colors);
+    Ok(())
+}
+
+fn parse_color(value: &str) -> Option<Rgb> {
+    let value = value.trim().trim_start_matches('#');
+    if value.len() != 6 {
+        return None;
+    }
+
+    let rgb = u32::from_str_radix(value, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Reads lines from stdin on a separate thread so the emulator never blocks on them
+fn spawn_hotkey_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
 
-    let mut file = std::fs::OpenOptions::new()
-        .read(true)
-        .open("test_opcode.ch8")
-        .unwrap();
-    file.read_to_end(&mut bytes).unwrap();
+/// A font preset, or failing that a font file
+fn parse_font(value: &str) -> Result<FontSet, String> {
+    if let Ok(font) = value.parse() {
+        return Ok(font);
+    }
 
-    println!("{}", bytes.len());
+    let bytes =
+        std::fs::read(value).map_err(|err| format!("could not read font {value}: {err}"))?;
+    FontSet::from_bytes(&bytes).map_err(|err| format!("{value}: {err}"))
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|err| format!("could not create {}: {err}", path.display()))
+}
+
+fn run(args: RunArgs) -> Result<(), String> {
+    let bytes = std::fs::read(&args.rom)
+        .map_err(|err| format!("could not read {}: {err}", args.rom.display()))?;
+
+    if let Some(interpreter) = args.interpreter.clone() {
+        return run_vip(args, &bytes, &interpreter);
+    }
+
+    let mut machine = args.machine;
+    if let Some(font) = args.font {
+        machine.font = font;
+    }
+
+    let mut cpu = CPU::with_machine(machine, &bytes)?;
+    cpu.quirks = args.quirks;
+    cpu.stack_config = args.stack;
+    cpu.timing = args.timing;
+    cpu.unknown_opcodes = args.unknown_opcodes;
+    cpu.protection = args.protection;
+
+    if !args.headless {
+        let hotkeys = spawn_hotkey_reader();
+        let mut screenshots = 0;
+
+        let mut tracker = LimitTracker::new(Limits::unlimited());
+
+        loop {
+            let started = Instant::now();
+
+            if let Some(StopReason::Fault { pc, message }) = cpu.run_frame_limited(&mut tracker) {
+                return Err(report_crash(
+                    &cpu,
+                    args.crash_report.as_deref(),
+                    pc,
+                    &message,
+                ));
+            }
+            if cpu.take_frame().is_some() {
+                cpu.update();
+            }
+
+            while let Ok(line) = hotkeys.try_recv() {
+                if line.trim() == "p" {
+                    screenshots += 1;
+                    let path = numbered_path(&args.screenshot, screenshots);
+                    cpu.screenshot(&path, &args.png)
+                        .map_err(|err| format!("could not save {}: {err}", path.display()))?;
+                }
+            }
+
+            thread::sleep(FRAME_DURATION.saturating_sub(started.elapsed()));
+        }
+    }
+
+    let report = run_headless(&mut cpu, &args)?;
+    match report.reason {
+        StopReason::Fault { pc, message } => {
+            return Err(report_crash(
+                &cpu,
+                args.crash_report.as_deref(),
+                pc,
+                &message,
+            ))
+        }
+        StopReason::FrameLimit if !args.stop_on_halt => {}
+        reason => eprintln!("stopped after {} frames: {reason}", report.frames),
+    }
+
+    // A crash report already lists them
+    for diagnostic in &cpu.diagnostics {
+        eprintln!("warning: {diagnostic}");
+    }
+
+    Ok(())
+}
+
+/// Runs the ROM on an emulated COSMAC VIP with the original interpreter instead of the
+/// high level CPU
+fn run_vip(args: RunArgs, rom: &[u8], interpreter: &Path) -> Result<(), String> {
+    // The interpreter runs the ROM itself, none of the CPU's settings apply to it
+    if let Some(flag) = args.cpu_flags.first() {
+        return Err(format!("{flag} is not supported with --interpreter"));
+    }
+
+    let image = std::fs::read(interpreter)
+        .map_err(|err| format!("could not read {}: {err}", interpreter.display()))?;
+    let mut vip = Vip::new(&image, rom)?;
+
+    if !args.headless {
+        let mut shown = None;
+
+        loop {
+            let started = Instant::now();
+
+            vip.run_frame();
+            if shown.as_ref() != Some(&vip.buf) {
+                vip.update();
+                shown = Some(vip.buf.clone());
+            }
+
+            thread::sleep(FRAME_DURATION.saturating_sub(started.elapsed()));
+        }
+    }
+
+    let report = run_headless(&mut vip, &args)?;
+    if !matches!(report.reason, StopReason::FrameLimit) {
+        eprintln!("stopped after {} frames: {}", report.frames, report.reason);
+    }
+
+    Ok(())
+}
+
+/// Attaches the video and audio sinks asked for and runs the machine without a terminal
+fn run_headless<E: Emulator>(emulator: &mut E, args: &RunArgs) -> Result<RunReport, String> {
+    let format = match (args.format, &args.output) {
+        (Some(ImageFormat::Png(_)), _) => ImageFormat::Png(args.png),
+        (Some(format), _) => format,
+        (None, Some(output)) => match ImageFormat::from_path(output) {
+            Some(ImageFormat::Png(_)) => ImageFormat::Png(args.png),
+            Some(format) => format,
+            None => {
+                return Err(format!(
+                    "cannot guess the format of {}, pass --format",
+                    output.display()
+                ))
+            }
+        },
+        (None, None) => ImageFormat::Ascii,
+    };
+
+    emulator.output().set_limit(args.limits.max_output_bytes);
+
+    if let Some(path) = &args.y4m {
+        let out = emulator.output().wrap(create_file(path)?);
+        let writer = Y4mWriter::new(
+            out,
+            WIDTH as usize,
+            HEIGHT as usize,
+            args.png.scale,
+            args.png.palette,
+        )
+        .map_err(|err| format!("could not write {}: {err}", path.display()))?;
+        emulator.sinks().push(Box::new(writer));
+    }
+
+    if let Some(path) = &args.wav {
+        let writer = WavWriter::new(emulator.output().wrap(create_file(path)?))
+            .map_err(|err| format!("could not write {}: {err}", path.display()))?;
+        emulator.sinks().push(Box::new(writer));
+    }
+
+    let options = HeadlessOptions {
+        frames: args.frames,
+        input: args.input.clone(),
+        limits: args.limits,
+        stop_when_halted: args.stop_on_halt,
+        every: args.every,
+        output: args.output.clone(),
+        format,
+        screenshot_frames: args.screenshot_frames.clone(),
+        screenshot_path: args.screenshot.clone(),
+        screenshot_options: args.png,
+        gif: args.gif.clone().map(|path| {
+            let (first, last) = args.gif_frames.unwrap_or((1, args.frames));
+            GifCapture {
+                path,
+                frames: first..=last,
+                options: GifOptions {
+                    scale: args.png.scale,
+                    palette: args.png.palette,
+                },
+            }
+        }),
+    };
+
+    headless::run_headless(emulator, &options)
+        .map_err(|err| format!("could not write frame: {err}"))
+}
+
+/// Writes a crash report to the given file or stderr, returning the error to exit with
+fn report_crash(cpu: &CPU, path: Option<&Path>, pc: u16, message: &str) -> String {
+    let error = format!("crashed at {pc:#05x}: {message}");
+
+    let written = match path {
+        Some(path) => cpu.save_crash_report(path, pc, message),
+        None => cpu.write_crash_report(&mut std::io::stderr().lock(), pc, message),
+    };
+
+    match (written, path) {
+        (Ok(()), Some(path)) => format!("{error}, crash report written to {}", path.display()),
+        (Ok(()), None) => error,
+        (Err(err), _) => format!("{error}, could not write the crash report: {err}"),
+    }
+}
 
-    let mut cpu = lib::cpu::CPU::new_with_memory(&bytes);
+fn test(args: TestArgs) -> Result<(), String> {
+    let manifest_path = args
+        .manifest
+        .unwrap_or_else(|| args.dir.join("manifest.ini"));
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .map_err(|err| format!("could not read {}: {err}", manifest_path.display()))?;
+    let cases = suite::parse_manifest(&manifest)?;
 
-    // cpu.setannn(0x050);
+    let results = suite::run_suite(&args.dir, &cases, args.jobs);
 
-    // cpu.set6xnn(0, 65);
+    let mut report = Vec::new();
+    if args.junit {
+        suite::write_junit(&mut report, &results)
+    } else {
+        suite::write_json(&mut report, &results)
+    }
+    .map_err(|err| format!("could not write report: {err}"))?;
 
-    // cpu.set6xnn(1, 5);
+    match &args.output {
+        Some(path) => std::fs::write(path, &report)
+            .map_err(|err| format!("could not write {}: {err}", path.display()))?,
+        None => print!("{}", String::from_utf8_lossy(&report)),
+    }
 
-    // cpu.draw(0, 1, 5);
+    let failed: Vec<&TestResult> = results.iter().filter(|result| !result.passed()).collect();
+    for result in &failed {
+        eprintln!(
+            "FAIL {}: {}",
+            result.rom.display(),
+            result.failures.join("; ")
+        );
+    }
+    eprintln!(
+        "{} passed, {} failed",
+        results.len() - failed.len(),
+        failed.len()
+    );
 
-    // println!("################################################################");
-    cpu.run();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} tests failed",
+            failed.len(),
+            results.len()
+        ))
+    }
 }